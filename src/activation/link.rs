@@ -0,0 +1,169 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::Outcome;
+use crate::{ActivationConfig, Error, ErrorKind, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 签名密钥
+pub struct SigningKey {
+    pub kid: String,
+    pub secret: String,
+}
+
+/// 解析配置中的签名密钥
+pub fn signing_keys(cfg: &ActivationConfig) -> Result<Vec<SigningKey>> {
+    cfg.link_keys
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.split_once(':') {
+            Some((kid, secret)) if !kid.is_empty() && !kid.contains('.') && !secret.is_empty() => {
+                Ok(SigningKey {
+                    kid: kid.to_string(),
+                    secret: secret.to_string(),
+                })
+            }
+            _ => Err(Error::from_str(
+                ErrorKind::Activation,
+                "激活链接密钥的格式应为 kid:secret",
+            )),
+        })
+        .collect()
+}
+
+fn mac(key: &SigningKey, data: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(key.secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(data.as_bytes());
+    mac
+}
+
+/// 生成激活令牌，格式为 `kid.payload.signature`。未配置密钥时返回 `None`
+pub fn sign(cfg: &ActivationConfig, email: &str) -> Result<Option<String>> {
+    let keys = signing_keys(cfg)?;
+    let key = match keys.first() {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+
+    let expires_at = super::now() + cfg.ttl_secs;
+    let payload = URL_SAFE_NO_PAD.encode(format!("{}\n{}", expires_at, email));
+    let data = format!("{}.{}", key.kid, payload);
+    let sig = URL_SAFE_NO_PAD.encode(mac(key, &data).finalize().into_bytes());
+
+    Ok(Some(format!("{}.{}", data, sig)))
+}
+
+/// 校验激活令牌，不需要访问激活码存储
+///
+/// 令牌有效时返回其中的邮箱，否则返回失败的原因。
+pub fn verify(cfg: &ActivationConfig, token: &str) -> Result<std::result::Result<String, Outcome>> {
+    let keys = signing_keys(cfg)?;
+
+    let mut parts = token.splitn(3, '.');
+    let (kid, payload, sig) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(kid), Some(payload), Some(sig)) => (kid, payload, sig),
+        _ => return Ok(Err(Outcome::WrongCode)),
+    };
+    let key = match keys.iter().find(|k| k.kid == kid) {
+        Some(key) => key,
+        None => return Ok(Err(Outcome::WrongCode)),
+    };
+    let sig = match URL_SAFE_NO_PAD.decode(sig) {
+        Ok(sig) => sig,
+        Err(_) => return Ok(Err(Outcome::WrongCode)),
+    };
+    if mac(key, &format!("{}.{}", kid, payload))
+        .verify_slice(&sig)
+        .is_err()
+    {
+        return Ok(Err(Outcome::WrongCode));
+    }
+
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|p| String::from_utf8(p).ok());
+    let parsed = payload.as_deref().and_then(|p| {
+        let (t, email) = p.split_once('\n')?;
+        Some((t.parse::<u64>().ok()?, email))
+    });
+    match parsed {
+        Some((t, email)) if super::now() < t => Ok(Ok(email.to_string())),
+        Some(_) => Ok(Err(Outcome::Expired)),
+        None => Ok(Err(Outcome::WrongCode)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{activation::Outcome, ActivationConfig};
+
+    fn cfg(link_keys: &str) -> ActivationConfig {
+        ActivationConfig {
+            link_keys: link_keys.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let cfg = cfg("k1:axum.rs");
+        let token = super::sign(&cfg, "team@axum.rs").unwrap().unwrap();
+        assert_eq!(
+            super::verify(&cfg, &token).unwrap(),
+            Ok("team@axum.rs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_no_keys() {
+        assert!(super::sign(&cfg(""), "team@axum.rs").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let token = super::sign(&cfg("k1:old"), "team@axum.rs")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            super::verify(&cfg("k2:new,k1:old"), &token).unwrap(),
+            Ok("team@axum.rs".to_string())
+        );
+        assert_eq!(
+            super::verify(&cfg("k2:new"), &token).unwrap(),
+            Err(Outcome::WrongCode)
+        );
+    }
+
+    #[test]
+    fn test_tampered_token() {
+        let cfg = cfg("k1:axum.rs");
+        let token = super::sign(&cfg, "team@axum.rs").unwrap().unwrap();
+        let (data, _) = token.rsplit_once('.').unwrap();
+        let forged = format!("{}.{}", data, "AAAA");
+        assert_eq!(
+            super::verify(&cfg, &forged).unwrap(),
+            Err(Outcome::WrongCode)
+        );
+        assert_eq!(super::verify(&cfg, "foo").unwrap(), Err(Outcome::WrongCode));
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let cfg = ActivationConfig {
+            ttl_secs: 0,
+            ..cfg("k1:axum.rs")
+        };
+        let token = super::sign(&cfg, "team@axum.rs").unwrap().unwrap();
+        assert_eq!(super::verify(&cfg, &token).unwrap(), Err(Outcome::Expired));
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(super::signing_keys(&cfg("k1")).is_err());
+    }
+}
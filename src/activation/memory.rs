@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use super::{ActivationCodeStore, StoredCode};
+use crate::Result;
+
+/// 基于内存的激活码存储，仅适用于单实例部署
+#[derive(Default)]
+pub struct MemoryStore {
+    codes: RwLock<HashMap<String, StoredCode>>,
+    attempts: RwLock<HashMap<String, (u32, Instant)>>,
+}
+
+#[async_trait]
+impl ActivationCodeStore for MemoryStore {
+    async fn save(&self, email: &str, code: &StoredCode) -> Result<()> {
+        self.codes
+            .write()
+            .await
+            .insert(email.to_string(), code.clone());
+        Ok(())
+    }
+
+    async fn get(&self, email: &str) -> Result<Option<StoredCode>> {
+        Ok(self.codes.read().await.get(email).cloned())
+    }
+
+    async fn remove(&self, email: &str) -> Result<()> {
+        self.codes.write().await.remove(email);
+        Ok(())
+    }
+
+    async fn consume(&self, email: &str, expected: &StoredCode) -> Result<bool> {
+        let mut codes = self.codes.write().await;
+        if codes.get(email) != Some(expected) {
+            return Ok(false);
+        }
+        codes.remove(email);
+        Ok(true)
+    }
+
+    async fn attempts(&self, email: &str) -> Result<u32> {
+        Ok(match self.attempts.read().await.get(email) {
+            Some((n, reset_at)) if *reset_at > Instant::now() => *n,
+            _ => 0,
+        })
+    }
+
+    async fn incr_attempts(&self, email: &str, window: Duration, max: u32) -> Result<u32> {
+        let mut attempts = self.attempts.write().await;
+        let now = Instant::now();
+        let entry = attempts
+            .entry(email.to_string())
+            .or_insert((0, now + window));
+        if entry.1 <= now {
+            *entry = (0, now + window);
+        }
+        entry.0 += 1;
+        if entry.0 == max {
+            entry.1 = now + window;
+        }
+        Ok(entry.0)
+    }
+
+    async fn reset_attempts(&self, email: &str) -> Result<()> {
+        self.attempts.write().await.remove(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{ActivationCodeStore, MemoryStore};
+    use crate::activation::StoredCode;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryStore::default();
+        store
+            .save(
+                "team@axum.rs",
+                &StoredCode::new("123456", Duration::from_secs(60)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("team@axum.rs").await.unwrap().unwrap().code,
+            "123456"
+        );
+        assert!(store.get("foo@axum.rs").await.unwrap().is_none());
+
+        let stored = store.get("team@axum.rs").await.unwrap().unwrap();
+        let other = StoredCode::new("654321", Duration::from_secs(60));
+        assert!(!store.consume("team@axum.rs", &other).await.unwrap());
+        assert!(store.consume("team@axum.rs", &stored).await.unwrap());
+        assert!(!store.consume("team@axum.rs", &stored).await.unwrap());
+        assert!(store.get("team@axum.rs").await.unwrap().is_none());
+
+        store.save("team@axum.rs", &stored).await.unwrap();
+        store.remove("team@axum.rs").await.unwrap();
+        assert!(store.get("team@axum.rs").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memory_attempts() {
+        let store = MemoryStore::default();
+        let window = Duration::from_secs(60);
+        assert_eq!(
+            store
+                .incr_attempts("team@axum.rs", window, 5)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            store
+                .incr_attempts("team@axum.rs", window, 5)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(store.attempts("team@axum.rs").await.unwrap(), 2);
+
+        // 达到上限时重新计时
+        let window = Duration::from_millis(200);
+        store.reset_attempts("team@axum.rs").await.unwrap();
+        store
+            .incr_attempts("team@axum.rs", window, 2)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        store
+            .incr_attempts("team@axum.rs", window, 2)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert_eq!(store.attempts("team@axum.rs").await.unwrap(), 2);
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert_eq!(store.attempts("team@axum.rs").await.unwrap(), 0);
+
+        store.reset_attempts("team@axum.rs").await.unwrap();
+        assert_eq!(store.attempts("team@axum.rs").await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use rand::{rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+use crate::{ActivationConfig, ActivationStoreKind, Error, ErrorKind, Result};
+
+pub mod link;
+pub mod memory;
+pub mod redis;
+
+/// 已保存的激活码
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StoredCode {
+    pub code: String,
+    /// 过期时间（UNIX 时间戳，秒）
+    pub expires_at: u64,
+}
+
+impl StoredCode {
+    pub fn new(code: &str, ttl: Duration) -> Self {
+        Self {
+            code: code.to_string(),
+            expires_at: now() + ttl.as_secs(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now() >= self.expires_at
+    }
+}
+
+/// 激活码存储
+#[async_trait]
+pub trait ActivationCodeStore: Send + Sync {
+    /// 保存邮箱对应的激活码，会覆盖之前的激活码
+    async fn save(&self, email: &str, code: &StoredCode) -> Result<()>;
+    /// 获取邮箱对应的激活码
+    async fn get(&self, email: &str) -> Result<Option<StoredCode>>;
+    /// 删除邮箱对应的激活码
+    async fn remove(&self, email: &str) -> Result<()>;
+    /// 保存的激活码与 `expected` 相同时删除，返回是否已删除
+    ///
+    /// 检查和删除是原子的，同一个激活码只有一个调用者能删除成功。
+    async fn consume(&self, email: &str, expected: &StoredCode) -> Result<bool>;
+    /// 获取邮箱的失败次数
+    async fn attempts(&self, email: &str) -> Result<u32>;
+    /// 增加邮箱的失败次数，返回增加后的次数
+    ///
+    /// 计数从第一次失败开始，在 `window` 之后自动清零；达到 `max` 时重新计时，即锁定 `window`。
+    async fn incr_attempts(&self, email: &str, window: Duration, max: u32) -> Result<u32>;
+    /// 清除邮箱的失败次数
+    async fn reset_attempts(&self, email: &str) -> Result<()>;
+}
+
+/// 校验结果
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Outcome {
+    Success,
+    /// 激活码已过期或不存在
+    Expired,
+    WrongCode,
+    TooManyAttempts,
+}
+
+/// 根据配置创建激活码存储
+pub async fn new_store(cfg: &ActivationConfig) -> Result<Arc<dyn ActivationCodeStore>> {
+    match cfg.store {
+        ActivationStoreKind::Memory => Ok(Arc::new(memory::MemoryStore::default())),
+        ActivationStoreKind::Redis => {
+            Ok(Arc::new(redis::RedisStore::connect(&cfg.redis_dsn).await?))
+        }
+    }
+}
+
+/// 生成激活码
+pub fn gen_code(cfg: &ActivationConfig) -> Result<String> {
+    let alphabet: Vec<char> = cfg.alphabet.chars().collect();
+    if alphabet.is_empty() || cfg.code_length == 0 {
+        return Err(Error::from_str(
+            ErrorKind::Activation,
+            "激活码的长度和字符集不能为空",
+        ));
+    }
+
+    let mut rng = OsRng;
+    Ok((0..cfg.code_length)
+        .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+        .collect())
+}
+
+/// 生成并保存激活码
+pub async fn issue(
+    store: &dyn ActivationCodeStore,
+    cfg: &ActivationConfig,
+    email: &str,
+) -> Result<String> {
+    let code = gen_code(cfg)?;
+    store
+        .save(
+            email,
+            &StoredCode::new(&code, Duration::from_secs(cfg.ttl_secs)),
+        )
+        .await?;
+    Ok(code)
+}
+
+/// 校验激活码，成功后激活码即失效
+///
+/// 先增加失败次数再校验，成功后清零，避免同时提交的多次猜测绕过次数限制。
+pub async fn verify(
+    store: &dyn ActivationCodeStore,
+    cfg: &ActivationConfig,
+    email: &str,
+    code: &str,
+) -> Result<Outcome> {
+    let attempts = store
+        .incr_attempts(
+            email,
+            Duration::from_secs(cfg.lockout_secs),
+            cfg.max_attempts,
+        )
+        .await?;
+    if attempts > cfg.max_attempts {
+        return Ok(Outcome::TooManyAttempts);
+    }
+
+    let stored = match store.get(email).await? {
+        Some(stored) => stored,
+        None => return Ok(Outcome::Expired),
+    };
+    if stored.is_expired() {
+        store.remove(email).await?;
+        return Ok(Outcome::Expired);
+    }
+
+    if verify_code(&stored.code, code) {
+        // 同时提交的正确激活码只有一个能激活成功
+        if !store.consume(email, &stored).await? {
+            return Ok(Outcome::Expired);
+        }
+        store.reset_attempts(email).await?;
+        return Ok(Outcome::Success);
+    }
+
+    if attempts >= cfg.max_attempts {
+        Ok(Outcome::TooManyAttempts)
+    } else {
+        Ok(Outcome::WrongCode)
+    }
+}
+
+/// 通过激活链接激活
+///
+/// 与输入激活码相同，受失败次数的限制，成功后激活码失效，链接也不能再次使用。
+pub async fn verify_link(
+    store: &dyn ActivationCodeStore,
+    cfg: &ActivationConfig,
+    token: &str,
+) -> Result<Outcome> {
+    let email = match link::verify(cfg, token)? {
+        Ok(email) => email,
+        Err(outcome) => return Ok(outcome),
+    };
+    let attempts = store
+        .incr_attempts(
+            &email,
+            Duration::from_secs(cfg.lockout_secs),
+            cfg.max_attempts,
+        )
+        .await?;
+    if attempts > cfg.max_attempts {
+        return Ok(Outcome::TooManyAttempts);
+    }
+
+    let stored = match store.get(&email).await? {
+        Some(stored) if !stored.is_expired() => stored,
+        _ => return Ok(Outcome::Expired),
+    };
+    if !store.consume(&email, &stored).await? {
+        return Ok(Outcome::Expired);
+    }
+    store.reset_attempts(&email).await?;
+    Ok(Outcome::Success)
+}
+
+/// 以固定时间比较激活码
+pub fn verify_code(expected: &str, actual: &str) -> bool {
+    expected.as_bytes().ct_eq(actual.as_bytes()).into()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{memory::MemoryStore, Outcome};
+    use crate::ActivationConfig;
+
+    #[test]
+    fn test_gen_code() {
+        let cfg = ActivationConfig {
+            code_length: 8,
+            alphabet: "ABC".to_string(),
+            ..Default::default()
+        };
+        let code = super::gen_code(&cfg).unwrap();
+        assert_eq!(code.chars().count(), 8);
+        assert!(code.chars().all(|c| "ABC".contains(c)));
+    }
+
+    #[test]
+    fn test_gen_code_empty_alphabet() {
+        let cfg = ActivationConfig {
+            alphabet: String::new(),
+            ..Default::default()
+        };
+        assert!(super::gen_code(&cfg).is_err());
+    }
+
+    #[test]
+    fn test_verify_code() {
+        assert!(super::verify_code("123456", "123456"));
+        assert!(!super::verify_code("123456", "123457"));
+        assert!(!super::verify_code("123456", "12345"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_single_use() {
+        let store = MemoryStore::default();
+        let cfg = ActivationConfig::default();
+        let code = super::issue(&store, &cfg, "team@axum.rs").await.unwrap();
+
+        let outcome = super::verify(&store, &cfg, "team@axum.rs", &code)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::Success);
+
+        let outcome = super::verify(&store, &cfg, "team@axum.rs", &code)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::Expired);
+    }
+
+    #[tokio::test]
+    async fn test_verify_expired() {
+        let store = MemoryStore::default();
+        let cfg = ActivationConfig {
+            ttl_secs: 0,
+            ..Default::default()
+        };
+        let code = super::issue(&store, &cfg, "team@axum.rs").await.unwrap();
+
+        let outcome = super::verify(&store, &cfg, "team@axum.rs", &code)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::Expired);
+    }
+
+    #[tokio::test]
+    async fn test_verify_too_many_attempts() {
+        let store = MemoryStore::default();
+        let cfg = ActivationConfig {
+            alphabet: "0".to_string(),
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let code = super::issue(&store, &cfg, "team@axum.rs").await.unwrap();
+
+        let outcome = super::verify(&store, &cfg, "team@axum.rs", "x")
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::WrongCode);
+        let outcome = super::verify(&store, &cfg, "team@axum.rs", "x")
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::TooManyAttempts);
+
+        // 锁定后，即使激活码正确也不能激活
+        let outcome = super::verify(&store, &cfg, "team@axum.rs", &code)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::TooManyAttempts);
+    }
+
+    #[tokio::test]
+    async fn test_verify_link() {
+        let store = MemoryStore::default();
+        let cfg = ActivationConfig {
+            link_keys: "k1:axum.rs".to_string(),
+            ..Default::default()
+        };
+        let code = super::issue(&store, &cfg, "team@axum.rs").await.unwrap();
+        let token = super::link::sign(&cfg, "team@axum.rs").unwrap().unwrap();
+
+        let outcome = super::verify_link(&store, &cfg, &token).await.unwrap();
+        assert_eq!(outcome, Outcome::Success);
+
+        // 链接和激活码都只能使用一次
+        let outcome = super::verify_link(&store, &cfg, &token).await.unwrap();
+        assert_eq!(outcome, Outcome::Expired);
+        let outcome = super::verify(&store, &cfg, "team@axum.rs", &code)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::Expired);
+
+        let outcome = super::verify_link(&store, &cfg, "k1.bad.token")
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::WrongCode);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_verify_concurrent() {
+        let store = Arc::new(MemoryStore::default());
+        let cfg = Arc::new(ActivationConfig {
+            max_attempts: 3,
+            ..Default::default()
+        });
+        let code = super::issue(store.as_ref(), &cfg, "team@axum.rs")
+            .await
+            .unwrap();
+
+        // 同时提交的错误激活码不能超过次数限制
+        let guesses: Vec<_> = (0..20)
+            .map(|_| {
+                let (store, cfg) = (store.clone(), cfg.clone());
+                tokio::spawn(async move {
+                    super::verify(store.as_ref(), &cfg, "team@axum.rs", "wrong")
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        let mut wrong = 0;
+        for guess in guesses {
+            if guess.await.unwrap() == Outcome::WrongCode {
+                wrong += 1;
+            }
+        }
+        assert_eq!(wrong, 2);
+        let outcome = super::verify(store.as_ref(), &cfg, "team@axum.rs", &code)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::TooManyAttempts);
+
+        // 同时提交的正确激活码只有一个能激活成功
+        let store = Arc::new(MemoryStore::default());
+        let code = super::issue(store.as_ref(), &cfg, "team@axum.rs")
+            .await
+            .unwrap();
+        let submits: Vec<_> = (0..3)
+            .map(|_| {
+                let (store, cfg, code) = (store.clone(), cfg.clone(), code.clone());
+                tokio::spawn(async move {
+                    super::verify(store.as_ref(), &cfg, "team@axum.rs", &code)
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        let mut success = 0;
+        for submit in submits {
+            if submit.await.unwrap() == Outcome::Success {
+                success += 1;
+            }
+        }
+        assert_eq!(success, 1);
+    }
+}
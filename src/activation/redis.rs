@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use ::redis::{aio::ConnectionManager, AsyncCommands, Client, Script};
+use async_trait::async_trait;
+
+use super::{ActivationCodeStore, StoredCode};
+use crate::{Error, Result};
+
+const CODE_KEY_PREFIX: &str = "active_code:";
+const ATTEMPTS_KEY_PREFIX: &str = "active_attempts:";
+
+/// 增加失败次数。第一次失败和达到上限时设置有效期，没有有效期的旧数据也一并补上
+const INCR_ATTEMPTS_SCRIPT: &str = r"
+local n = redis.call('INCR', KEYS[1])
+if n == 1 or n == tonumber(ARGV[2]) or redis.call('TTL', KEYS[1]) < 0 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return n
+";
+
+/// 值与参数相同时删除
+const CONSUME_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+/// 基于 redis 的激活码存储，兼容任何实现了 redis 协议的服务
+pub struct RedisStore {
+    conn: ConnectionManager,
+}
+
+impl RedisStore {
+    pub async fn connect(dsn: &str) -> Result<Self> {
+        let client = Client::open(dsn).map_err(Error::from)?;
+        let conn = ConnectionManager::new(client).await.map_err(Error::from)?;
+        Ok(Self { conn })
+    }
+
+    fn code_key(email: &str) -> String {
+        format!("{}{}", CODE_KEY_PREFIX, email)
+    }
+
+    fn attempts_key(email: &str) -> String {
+        format!("{}{}", ATTEMPTS_KEY_PREFIX, email)
+    }
+}
+
+#[async_trait]
+impl ActivationCodeStore for RedisStore {
+    async fn save(&self, email: &str, code: &StoredCode) -> Result<()> {
+        let mut conn = self.conn.clone();
+        let value = serde_json::to_string(code).map_err(Error::from)?;
+        // 让 redis 在过期后自动删除
+        let ttl = code.expires_at.saturating_sub(super::now()).max(1) as usize;
+        conn.set_ex(Self::code_key(email), value, ttl)
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn get(&self, email: &str) -> Result<Option<StoredCode>> {
+        let mut conn = self.conn.clone();
+        let value: Option<String> = conn.get(Self::code_key(email)).await.map_err(Error::from)?;
+        match value {
+            Some(value) => Ok(Some(serde_json::from_str(&value).map_err(Error::from)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn remove(&self, email: &str) -> Result<()> {
+        let mut conn = self.conn.clone();
+        conn.del(Self::code_key(email)).await.map_err(Error::from)
+    }
+
+    async fn consume(&self, email: &str, expected: &StoredCode) -> Result<bool> {
+        let mut conn = self.conn.clone();
+        // 与保存时的序列化结果相同
+        let value = serde_json::to_string(expected).map_err(Error::from)?;
+        let deleted: u32 = Script::new(CONSUME_SCRIPT)
+            .key(Self::code_key(email))
+            .arg(value)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(Error::from)?;
+        Ok(deleted == 1)
+    }
+
+    async fn attempts(&self, email: &str) -> Result<u32> {
+        let mut conn = self.conn.clone();
+        let n: Option<u32> = conn
+            .get(Self::attempts_key(email))
+            .await
+            .map_err(Error::from)?;
+        Ok(n.unwrap_or_default())
+    }
+
+    async fn incr_attempts(&self, email: &str, window: Duration, max: u32) -> Result<u32> {
+        let mut conn = self.conn.clone();
+        Script::new(INCR_ATTEMPTS_SCRIPT)
+            .key(Self::attempts_key(email))
+            .arg(window.as_secs().max(1))
+            .arg(max)
+            .invoke_async(&mut conn)
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn reset_attempts(&self, email: &str) -> Result<()> {
+        let mut conn = self.conn.clone();
+        conn.del(Self::attempts_key(email))
+            .await
+            .map_err(Error::from)
+    }
+}
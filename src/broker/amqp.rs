@@ -0,0 +1,51 @@
+use async_trait::async_trait;
+
+use super::{MessageBroker, RawHandler};
+use crate::{
+    message::RawEnvelope,
+    rabbitmq::{consumer::Supervisor, handler, publisher::Publisher},
+    RabbitMQConfig, Result,
+};
+
+/// 基于 RabbitMQ 的消息代理
+pub struct AmqpBroker {
+    cfg: RabbitMQConfig,
+    publisher: Publisher,
+}
+
+impl AmqpBroker {
+    pub async fn connect(cfg: &RabbitMQConfig) -> Result<Self> {
+        Ok(Self {
+            cfg: cfg.clone(),
+            publisher: Publisher::connect(cfg).await?,
+        })
+    }
+
+    /// 创建消息代理，第一次发送消息时才连接
+    pub fn lazy(cfg: &RabbitMQConfig) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            publisher: Publisher::lazy(cfg),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageBroker for AmqpBroker {
+    async fn publish(&self, routing_key: &str, msg: &RawEnvelope) -> Result<()> {
+        self.publisher.publish_to(routing_key, msg).await
+    }
+
+    fn subscribe(&self, tag: &str, binding: &str, handler: RawHandler) -> Supervisor {
+        // 队列以 `binding` 绑定到交换机，延迟队列中的消息也以此重新投递
+        let cfg = RabbitMQConfig {
+            routing_key: binding.to_string(),
+            ..self.cfg.clone()
+        };
+        handler::supervise(cfg, tag, handler)
+    }
+
+    async fn close(&self) {
+        self.publisher.close().await
+    }
+}
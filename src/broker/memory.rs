@@ -0,0 +1,296 @@
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot, watch};
+
+use super::{MessageBroker, RawHandler};
+use crate::{
+    message::RawEnvelope,
+    rabbitmq::consumer::{ConsumerState, Supervisor},
+    Error, ErrorKind, Result,
+};
+
+struct Subscriber {
+    binding: String,
+    tx: mpsc::UnboundedSender<String>,
+}
+
+/// 进程内的消息代理，用于测试和本地开发
+///
+/// 消息保存在内存中，按订阅顺序逐条处理。不支持重试，处理失败的消息记录在
+/// [`MemoryBroker::dead_letters`] 中。
+#[derive(Default)]
+pub struct MemoryBroker {
+    subscribers: RwLock<Vec<Subscriber>>,
+    dead_letters: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl MemoryBroker {
+    /// 处理失败的消息及失败原因
+    pub fn dead_letters(&self) -> Vec<(String, String)> {
+        self.dead_letters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl MessageBroker for MemoryBroker {
+    async fn publish(&self, routing_key: &str, msg: &RawEnvelope) -> Result<()> {
+        let payload = msg.to_payload()?;
+        let mut routed = false;
+        let mut subscribers = self.subscribers.write().unwrap_or_else(|e| e.into_inner());
+        // 顺便移除已停止的订阅者
+        subscribers.retain(|s| !s.tx.is_closed());
+        for s in subscribers.iter() {
+            if matches(&s.binding, routing_key) && s.tx.send(payload.clone()).is_ok() {
+                routed = true;
+            }
+        }
+
+        if routed {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::RabbitMQ,
+                format!("消息无法路由：{}", routing_key),
+                None,
+            ))
+        }
+    }
+
+    fn subscribe(&self, tag: &str, binding: &str, handler: RawHandler) -> Supervisor {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        self.subscribers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Subscriber {
+                binding: binding.to_string(),
+                tx,
+            });
+
+        let (state_tx, state_rx) = watch::channel(ConsumerState::Running);
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<Duration>();
+        let dead_letters = self.dead_letters.clone();
+        let tag = tag.to_string();
+
+        let handle = tokio::spawn(async move {
+            tracing::info!("消费者 {} 已启动", tag);
+            loop {
+                // 正在处理的消息完成后才会检查是否停止
+                tokio::select! {
+                    payload = rx.recv() => match payload {
+                        Some(payload) => {
+                            if let Err(err) = handler(payload.clone(), None).await {
+                                tracing::warn!("消息处理失败：{}", err.message);
+                                dead_letters
+                                    .lock()
+                                    .unwrap_or_else(|e| e.into_inner())
+                                    .push((payload, err.message));
+                            }
+                        }
+                        None => break,
+                    },
+                    _ = &mut shutdown_rx => break,
+                }
+            }
+            tracing::info!("消费者 {} 已停止", tag);
+            state_tx.send_replace(ConsumerState::Stopped);
+        });
+
+        Supervisor::new(state_rx, shutdown_tx, handle)
+    }
+
+    async fn close(&self) {
+        self.subscribers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// 路由键是否与 topic 绑定匹配
+pub fn matches(binding: &str, routing_key: &str) -> bool {
+    let pattern: Vec<&str> = binding.split('.').collect();
+    let words: Vec<&str> = routing_key.split('.').collect();
+    match_words(&pattern, &words)
+}
+
+fn match_words(pattern: &[&str], words: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => words.is_empty(),
+        Some((&"#", rest)) => (0..=words.len()).any(|i| match_words(rest, &words[i..])),
+        Some((&p, rest)) => match words.split_first() {
+            Some((&w, words)) if p == "*" || p == w => match_words(rest, words),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use axum::{
+        http::{header::ACCEPT_LANGUAGE, HeaderMap, StatusCode},
+        Extension, Form,
+    };
+    use tokio::sync::mpsc;
+
+    use super::MemoryBroker;
+    use crate::{
+        activation::{self, Outcome},
+        broker::MessageBroker,
+        form::RegisterForm,
+        handler,
+        message::{Envelope, Registry},
+        model::{state::AppState, user::ActiveCode},
+        rabbitmq::handler::raw,
+        worker::ActivationMailer,
+        EmailConfig, EmailTransportKind, ServeConfig, WorkerConfig,
+    };
+
+    #[test]
+    fn test_matches() {
+        assert!(super::matches("active-code", "active-code"));
+        assert!(!super::matches("active-code", "active-link"));
+        assert!(super::matches("user.*", "user.register"));
+        assert!(!super::matches("user.*", "user.register.email"));
+        assert!(super::matches("user.#", "user"));
+        assert!(super::matches("user.#", "user.register.email"));
+        assert!(super::matches("#.email", "user.register.email"));
+        assert!(super::matches("*.register.#", "user.register"));
+        assert!(!super::matches("*.register.#", "register"));
+        assert!(super::matches("#", "anything.at.all"));
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let broker = MemoryBroker::default();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let tx2 = tx.clone();
+        let a = broker.subscribe(
+            "A",
+            "user.*",
+            Arc::new(move |p: String, _| {
+                tx.send(("A", p)).ok();
+                Box::pin(async { Ok(()) })
+            }),
+        );
+        let b = broker.subscribe(
+            "B",
+            "#.email",
+            Arc::new(move |p: String, _| {
+                tx2.send(("B", p)).ok();
+                Box::pin(async { Ok(()) })
+            }),
+        );
+
+        let msg = Envelope::new(ActiveCode::default()).to_raw().unwrap();
+        broker.publish("user.email", &msg).await.unwrap();
+        let mut got = vec![rx.recv().await.unwrap().0, rx.recv().await.unwrap().0];
+        got.sort();
+        assert_eq!(got, vec!["A", "B"]);
+
+        broker.publish("order.email", &msg).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().0, "B");
+
+        assert!(broker.publish("order.created", &msg).await.is_err());
+
+        a.shutdown(Duration::from_secs(1)).await;
+        b.shutdown(Duration::from_secs(1)).await;
+        assert!(broker.publish("user.email", &msg).await.is_err());
+    }
+
+    /// 注册后，激活邮件中的激活码可以用来激活
+    #[tokio::test]
+    async fn test_register_flow() {
+        let cfg: ServeConfig = serde_json::from_value(serde_json::json!({
+            "web": { "addr": "127.0.0.1:9527" },
+            "rabbitmq": {
+                "dsn": "",
+                "exchange_name": "axum-rs",
+                "queue_name": "user-register",
+                "routing_key": "active-code",
+                "broker": "memory",
+            },
+        }))
+        .unwrap();
+
+        let worker_cfg = WorkerConfig {
+            rabbitmq: cfg.rabbitmq.clone(),
+            email: EmailConfig {
+                username: "noreply@axum.rs".to_string(),
+                transport: EmailTransportKind::Memory,
+                ..Default::default()
+            },
+            template: Default::default(),
+        };
+        let mailer = Arc::new(ActivationMailer::new(&worker_cfg).unwrap());
+
+        let broker: Arc<dyn MessageBroker> = Arc::new(MemoryBroker::default());
+        let mut registry = Registry::default();
+        ActiveCode::register(&mut registry);
+        let consumer = broker.subscribe(
+            "MAIL",
+            &cfg.rabbitmq.routing_key,
+            raw::<ActiveCode, _>(Arc::new(registry), mailer.clone()),
+        );
+
+        let state = Arc::new(AppState {
+            store: activation::new_store(&cfg.activation).await.unwrap(),
+            broker: broker.clone(),
+            outbox: None,
+            consumer_state: Some(consumer.state()),
+            cfg,
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_LANGUAGE, "en-US,en;q=0.9".parse().unwrap());
+        let (status, _, _) = handler::register(
+            Extension(state.clone()),
+            headers,
+            Form(RegisterForm {
+                username: "axum".to_string(),
+                email: "team@axum.rs".to_string(),
+                locale: None,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::FOUND);
+
+        let sent = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let sent = mailer.mailer().sent();
+                if !sent.is_empty() {
+                    break sent;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].from.as_deref(), Some("noreply@axum.rs"));
+        assert_eq!(sent[0].to, vec!["team@axum.rs".to_string()]);
+
+        // 邮件中的激活码就是保存的激活码
+        let code = state.store.get("team@axum.rs").await.unwrap().unwrap().code;
+        assert!(sent[0].raw.contains(&code));
+        let outcome = activation::verify(
+            state.store.as_ref(),
+            &state.cfg.activation,
+            "team@axum.rs",
+            &code,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, Outcome::Success);
+
+        consumer.shutdown(Duration::from_secs(1)).await;
+    }
+}
@@ -0,0 +1,52 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{
+    message::{Envelope, Message, RawEnvelope},
+    rabbitmq::{consumer::Supervisor, handler::Publish},
+    BrokerKind, RabbitMQConfig, Result,
+};
+
+pub mod amqp;
+pub mod memory;
+
+/// 处理原始载荷的函数，参数为载荷和消息的 ID（如 AMQP 的 `message_id` 属性）
+///
+/// 返回可重试的错误时稍后重试，其他错误转到死信队列。
+pub type RawHandler = Arc<
+    dyn Fn(String, Option<String>) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// 消息代理
+#[async_trait]
+pub trait MessageBroker: Send + Sync {
+    /// 以 `routing_key` 发送消息，确认消息已被路由到至少一个订阅者后才返回
+    async fn publish(&self, routing_key: &str, msg: &RawEnvelope) -> Result<()>;
+    /// 订阅路由键与 `binding` 匹配的消息
+    ///
+    /// `binding` 使用 topic 交换机的规则：`*` 匹配一个单词，`#` 匹配零个或多个单词。
+    fn subscribe(&self, tag: &str, binding: &str, handler: RawHandler) -> Supervisor;
+    /// 关闭连接
+    async fn close(&self);
+}
+
+#[async_trait]
+impl<T: Message + Send + Sync + 'static> Publish<T> for dyn MessageBroker {
+    async fn publish(&self, routing_key: &str, msg: Envelope<T>) -> Result<()> {
+        MessageBroker::publish(self, routing_key, &msg.to_raw()?).await
+    }
+}
+
+/// 根据配置创建消息代理
+///
+/// `lazy` 为 `true` 时启动时不连接，第一次发送消息时才连接，连接失败由调用者稍后重试。
+pub async fn new_broker(cfg: &RabbitMQConfig, lazy: bool) -> Result<Arc<dyn MessageBroker>> {
+    match cfg.broker {
+        BrokerKind::Amqp if lazy => Ok(Arc::new(amqp::AmqpBroker::lazy(cfg))),
+        BrokerKind::Amqp => Ok(Arc::new(amqp::AmqpBroker::connect(cfg).await?)),
+        BrokerKind::Memory => Ok(Arc::new(memory::MemoryBroker::default())),
+    }
+}
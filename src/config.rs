@@ -1,25 +1,451 @@
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{Error, Result};
+use crate::{Error, ErrorKind, Result};
 
 #[derive(Deserialize, Clone)]
 pub struct WebConfig {
     pub addr: String,
+    /// 对外访问的地址，用于生成激活链接。为空时使用 `http://{addr}`
+    #[serde(default)]
+    pub base_url: String,
+}
+
+impl WebConfig {
+    pub fn base_url(&self) -> String {
+        if self.base_url.is_empty() {
+            format!("http://{}", self.addr)
+        } else {
+            self.base_url.trim_end_matches('/').to_string()
+        }
+    }
+}
+
+/// 消息的投递模式
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryMode {
+    /// 服务器重启后丢失
+    Transient,
+    /// 持久化到磁盘，需配合持久化的队列使用
+    Persistent,
+}
+
+/// 队列和消息的选项
+#[derive(Deserialize, Clone, Debug)]
+pub struct QueueOptions {
+    /// 队列和交换机在服务器重启后是否保留
+    ///
+    /// 旧版本的队列不是持久化的，升级时需要先删除旧队列，见 README。
+    #[serde(default = "QueueOptions::default_durable")]
+    pub durable: bool,
+    /// 最后一个消费者断开后是否删除队列
+    #[serde(default)]
+    pub auto_delete: bool,
+    /// 是否仅允许当前连接使用
+    #[serde(default)]
+    pub exclusive: bool,
+    #[serde(default = "QueueOptions::default_delivery_mode")]
+    pub delivery_mode: DeliveryMode,
+    /// 消息的有效期（毫秒），对应 `x-message-ttl`
+    #[serde(default)]
+    pub message_ttl: Option<u64>,
+    /// 队列的最大长度，对应 `x-max-length`
+    #[serde(default)]
+    pub max_length: Option<u64>,
+    /// 超出最大长度时的策略：`drop-head`、`reject-publish` 或 `reject-publish-dlx`，对应 `x-overflow`
+    #[serde(default)]
+    pub overflow: Option<String>,
+    /// 队列类型：`classic` 或 `quorum`，对应 `x-queue-type`
+    #[serde(default)]
+    pub queue_type: Option<String>,
+}
+
+impl QueueOptions {
+    fn default_durable() -> bool {
+        true
+    }
+    fn default_delivery_mode() -> DeliveryMode {
+        DeliveryMode::Persistent
+    }
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            durable: Self::default_durable(),
+            auto_delete: false,
+            exclusive: false,
+            delivery_mode: Self::default_delivery_mode(),
+            message_ttl: None,
+            max_length: None,
+            overflow: None,
+            queue_type: None,
+        }
+    }
+}
+
+/// 消息代理
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BrokerKind {
+    /// RabbitMQ
+    Amqp,
+    /// 进程内的消息代理，只能在同时运行 WEB 服务和消费者时使用
+    Memory,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct RabbitMQConfig {
+    /// 消息代理
+    #[serde(default = "RabbitMQConfig::default_broker")]
+    pub broker: BrokerKind,
     pub dsn: String,
     pub exchange_name: String,
     pub queue_name: String,
     pub routing_key: String,
+    /// 队列和消息的选项
+    #[serde(default)]
+    pub queue: QueueOptions,
+    /// 发布者保持的管道数量
+    #[serde(default = "RabbitMQConfig::default_channel_pool_size")]
+    pub channel_pool_size: usize,
+    /// 消费者重连的最短等待时间（秒）
+    #[serde(default = "RabbitMQConfig::default_reconnect_min_secs")]
+    pub reconnect_min_secs: u64,
+    /// 消费者重连的最长等待时间（秒）
+    #[serde(default = "RabbitMQConfig::default_reconnect_max_secs")]
+    pub reconnect_max_secs: u64,
+    /// 死信交换机，为空时使用 `{exchange_name}.dlx`
+    #[serde(default)]
+    pub dead_letter_exchange: String,
+    /// 死信队列，为空时使用 `{queue_name}.dlq`
+    #[serde(default)]
+    pub dead_letter_queue: String,
+    /// 重试的等待时间（秒），多个用逗号分隔。第 N 次重试使用第 N 个等待时间，超出时使用最后一个
+    #[serde(default = "RabbitMQConfig::default_retry_delays")]
+    pub retry_delays: String,
+    /// 最大重试次数，超过后转到死信队列
+    #[serde(default = "RabbitMQConfig::default_max_retries")]
+    pub max_retries: u32,
+    /// 消费者预取的消息数量
+    #[serde(default = "RabbitMQConfig::default_prefetch_count")]
+    pub prefetch_count: u16,
+    /// 同时处理的消息数量
+    #[serde(default = "RabbitMQConfig::default_concurrency")]
+    pub concurrency: usize,
+    /// 停止时等待正在处理的消息完成的最长时间（秒）
+    #[serde(default = "RabbitMQConfig::default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
 }
 
-#[derive(Deserialize, Serialize, Default, Clone)]
+impl RabbitMQConfig {
+    fn default_broker() -> BrokerKind {
+        BrokerKind::Amqp
+    }
+    fn default_channel_pool_size() -> usize {
+        4
+    }
+    fn default_reconnect_min_secs() -> u64 {
+        1
+    }
+    fn default_reconnect_max_secs() -> u64 {
+        60
+    }
+
+    fn default_retry_delays() -> String {
+        "10,60,600".to_string()
+    }
+    fn default_max_retries() -> u32 {
+        3
+    }
+    fn default_prefetch_count() -> u16 {
+        10
+    }
+    fn default_concurrency() -> usize {
+        4
+    }
+    fn default_shutdown_timeout_secs() -> u64 {
+        30
+    }
+
+    /// 解析重试的等待时间
+    pub fn retry_delays(&self) -> Result<Vec<u64>> {
+        self.retry_delays
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<u64>()
+                    .map_err(|_| Error::from_str(ErrorKind::Config, "重试等待时间必须是整数"))
+            })
+            .collect()
+    }
+
+    pub fn dead_letter_exchange(&self) -> String {
+        if self.dead_letter_exchange.is_empty() {
+            format!("{}.dlx", self.exchange_name)
+        } else {
+            self.dead_letter_exchange.clone()
+        }
+    }
+
+    pub fn dead_letter_queue(&self) -> String {
+        if self.dead_letter_queue.is_empty() {
+            format!("{}.dlq", self.queue_name)
+        } else {
+            self.dead_letter_queue.clone()
+        }
+    }
+}
+
+/// 发送邮件的方式
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    /// 通过 SMTP 服务器发送
+    Smtp,
+    /// 写入 `file_dir` 目录下的 `.eml` 文件
+    File,
+    /// 保存在内存中，用于测试
+    Memory,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct EmailConfig {
+    /// 发送方式
+    #[serde(default = "EmailConfig::default_transport")]
+    pub transport: EmailTransportKind,
+    #[serde(default)]
     pub username: String,
+    /// SMTP 密码，不会被序列化
+    #[serde(default, skip_serializing)]
     pub password: String,
+    #[serde(default)]
     pub host: String,
+    /// SMTP 端口，为 0 时使用默认端口
+    #[serde(default)]
+    pub port: u16,
+    /// 是否使用 TLS 连接 SMTP 服务器
+    #[serde(default = "EmailConfig::default_tls")]
+    pub tls: bool,
+    /// 使用文件发送时，邮件保存的目录
+    #[serde(default = "EmailConfig::default_file_dir")]
+    pub file_dir: String,
+    #[serde(default)]
+    pub attachment: AttachmentConfig,
+    #[serde(default)]
+    pub dkim: DkimConfig,
+}
+
+impl EmailConfig {
+    fn default_transport() -> EmailTransportKind {
+        EmailTransportKind::Smtp
+    }
+    fn default_tls() -> bool {
+        true
+    }
+    fn default_file_dir() -> String {
+        "mails".to_string()
+    }
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            transport: Self::default_transport(),
+            username: String::new(),
+            password: String::new(),
+            host: String::new(),
+            port: 0,
+            tls: Self::default_tls(),
+            file_dir: Self::default_file_dir(),
+            attachment: AttachmentConfig::default(),
+            dkim: DkimConfig::default(),
+        }
+    }
+}
+
+/// DKIM 签名算法
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DkimAlgorithm {
+    Rsa,
+    Ed25519,
+}
+
+/// DKIM 签名
+#[derive(Deserialize, Serialize, Clone)]
+pub struct DkimConfig {
+    /// DNS 中公钥记录的选择器，为空时不签名
+    #[serde(default)]
+    pub selector: String,
+    /// 签名的域名，为空时使用发件人的域名
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default = "DkimConfig::default_algorithm")]
+    pub algorithm: DkimAlgorithm,
+    /// 私钥。RSA 为 PKCS#1 或 PKCS#8 PEM 格式，Ed25519 为 PKCS#8 PEM 格式，
+    /// 或 32 字节私钥（也可以与公钥拼接为 64 字节）的 Base64
+    #[serde(default, skip_serializing)]
+    pub private_key: String,
+    /// 私钥文件的路径，未设置 `private_key` 时使用
+    #[serde(default)]
+    pub private_key_path: String,
+    /// 参与签名的邮件头，多个用逗号分隔
+    #[serde(default = "DkimConfig::default_headers")]
+    pub headers: String,
+}
+
+impl DkimConfig {
+    fn default_algorithm() -> DkimAlgorithm {
+        DkimAlgorithm::Rsa
+    }
+    fn default_headers() -> String {
+        "From,To,Subject,Date,Message-ID".to_string()
+    }
+
+    /// 是否启用签名
+    pub fn enabled(&self) -> bool {
+        !self.selector.is_empty()
+    }
+
+    /// 解析参与签名的邮件头
+    pub fn headers(&self) -> Vec<String> {
+        self.headers
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Default for DkimConfig {
+    fn default() -> Self {
+        Self {
+            selector: String::new(),
+            domain: String::new(),
+            algorithm: Self::default_algorithm(),
+            private_key: String::new(),
+            private_key_path: String::new(),
+            headers: Self::default_headers(),
+        }
+    }
+}
+
+/// 邮件附件
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AttachmentConfig {
+    /// 以路径引用的附件所在的目录，附件不能位于该目录之外
+    #[serde(default = "AttachmentConfig::default_dir")]
+    pub dir: String,
+    /// 以 ID 引用的附件所在的目录，文件名即 ID
+    #[serde(default = "AttachmentConfig::default_blob_dir")]
+    pub blob_dir: String,
+    /// 单个附件的最大字节数
+    #[serde(default = "AttachmentConfig::default_max_size")]
+    pub max_size: u64,
+    /// 一封邮件中所有附件的最大字节数
+    #[serde(default = "AttachmentConfig::default_max_total_size")]
+    pub max_total_size: u64,
+}
+
+impl AttachmentConfig {
+    fn default_dir() -> String {
+        "attachments".to_string()
+    }
+    fn default_blob_dir() -> String {
+        "blobs".to_string()
+    }
+    fn default_max_size() -> u64 {
+        10 * 1024 * 1024
+    }
+    fn default_max_total_size() -> u64 {
+        20 * 1024 * 1024
+    }
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            dir: Self::default_dir(),
+            blob_dir: Self::default_blob_dir(),
+            max_size: Self::default_max_size(),
+            max_total_size: Self::default_max_total_size(),
+        }
+    }
+}
+
+/// 激活码存储方式
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivationStoreKind {
+    Memory,
+    Redis,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ActivationConfig {
+    /// 激活码长度
+    #[serde(default = "ActivationConfig::default_code_length")]
+    pub code_length: usize,
+    /// 激活码字符集
+    #[serde(default = "ActivationConfig::default_alphabet")]
+    pub alphabet: String,
+    #[serde(default = "ActivationConfig::default_store")]
+    pub store: ActivationStoreKind,
+    /// 存储方式为 redis 时使用
+    #[serde(default)]
+    pub redis_dsn: String,
+    /// 激活码有效期（秒）
+    #[serde(default = "ActivationConfig::default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// 每个邮箱允许的最大失败次数
+    #[serde(default = "ActivationConfig::default_max_attempts")]
+    pub max_attempts: u32,
+    /// 失败次数的统计窗口，也是达到最大失败次数后的锁定时长（秒）
+    #[serde(default = "ActivationConfig::default_lockout_secs")]
+    pub lockout_secs: u64,
+    /// 激活链接的签名密钥，格式为 `kid:secret`，多个密钥用逗号分隔。
+    /// 第一个密钥用于签名，其余的仅用于校验，方便轮换密钥。为空时不生成激活链接
+    #[serde(default)]
+    pub link_keys: String,
+}
+
+impl ActivationConfig {
+    fn default_code_length() -> usize {
+        6
+    }
+    fn default_alphabet() -> String {
+        "0123456789".to_string()
+    }
+    fn default_store() -> ActivationStoreKind {
+        ActivationStoreKind::Memory
+    }
+    fn default_ttl_secs() -> u64 {
+        15 * 60
+    }
+    fn default_max_attempts() -> u32 {
+        5
+    }
+    fn default_lockout_secs() -> u64 {
+        15 * 60
+    }
+}
+
+impl Default for ActivationConfig {
+    fn default() -> Self {
+        Self {
+            code_length: Self::default_code_length(),
+            alphabet: Self::default_alphabet(),
+            store: Self::default_store(),
+            redis_dsn: String::new(),
+            ttl_secs: Self::default_ttl_secs(),
+            max_attempts: Self::default_max_attempts(),
+            lockout_secs: Self::default_lockout_secs(),
+            link_keys: String::new(),
+        }
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -27,15 +453,97 @@ pub struct Config {
     pub web: WebConfig,
     pub rabbitmq: RabbitMQConfig,
     pub email: EmailConfig,
+    #[serde(default)]
+    pub activation: ActivationConfig,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+    #[serde(default)]
+    pub template: TemplateConfig,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct OutboxConfig {
+    /// SQLite 数据库文件的路径，为空时不使用发件箱，注册时直接发送消息
+    #[serde(default)]
+    pub path: String,
+    /// 检查未发送消息的间隔（秒）
+    #[serde(default = "OutboxConfig::default_relay_interval_secs")]
+    pub relay_interval_secs: u64,
+    /// 每次发送的消息数量
+    #[serde(default = "OutboxConfig::default_batch_size")]
+    pub batch_size: usize,
+}
+
+impl OutboxConfig {
+    fn default_relay_interval_secs() -> u64 {
+        5
+    }
+    fn default_batch_size() -> usize {
+        100
+    }
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            relay_interval_secs: Self::default_relay_interval_secs(),
+            batch_size: Self::default_batch_size(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct TemplateConfig {
+    /// 模板目录，其中的模板覆盖内置的同名模板。为空时只使用内置模板
+    #[serde(default)]
+    pub dir: String,
+}
+
+/// WEB 服务所需的配置
+#[derive(Deserialize, Clone)]
+pub struct ServeConfig {
+    pub web: WebConfig,
+    pub rabbitmq: RabbitMQConfig,
+    #[serde(default)]
+    pub activation: ActivationConfig,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+}
+
+/// 邮件消费者所需的配置
+#[derive(Deserialize, Clone)]
+pub struct WorkerConfig {
+    pub rabbitmq: RabbitMQConfig,
+    pub email: EmailConfig,
+    #[serde(default)]
+    pub template: TemplateConfig,
+}
+
+/// 从环境变量中读取配置
+fn from_env<T: DeserializeOwned>() -> Result<T> {
+    config::Config::builder()
+        .add_source(config::Environment::default())
+        .build()
+        .map_err(Error::from)?
+        .try_deserialize()
+        .map_err(Error::from)
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
-        config::Config::builder()
-            .add_source(config::Environment::default())
-            .build()
-            .map_err(Error::from)?
-            .try_deserialize()
-            .map_err(Error::from)
+        from_env()
+    }
+}
+
+impl ServeConfig {
+    pub fn from_env() -> Result<Self> {
+        from_env()
+    }
+}
+
+impl WorkerConfig {
+    pub fn from_env() -> Result<Self> {
+        from_env()
     }
 }
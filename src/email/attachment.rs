@@ -0,0 +1,249 @@
+//! 读取邮件附件
+//!
+//! 附件以路径或 ID 引用，发送时才读取，并检查大小、检测 MIME 类型。
+
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use lettre::message::{header::ContentType, Attachment as Part, SinglePart};
+
+use crate::{
+    model::email::{Attachment, AttachmentSource},
+    AttachmentConfig, Error, ErrorKind, Result,
+};
+
+/// 已读取的附件
+pub struct Loaded {
+    pub filename: String,
+    pub content_type: ContentType,
+    pub content_id: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Loaded {
+    pub fn into_part(self) -> SinglePart {
+        match self.content_id {
+            Some(cid) => Part::new_inline(cid).body(self.body, self.content_type),
+            None => Part::new(self.filename).body(self.body, self.content_type),
+        }
+    }
+}
+
+/// 读取邮件的所有附件
+pub fn load_all(cfg: &AttachmentConfig, attachments: &[Attachment]) -> Result<Vec<Loaded>> {
+    let mut total = 0;
+    attachments
+        .iter()
+        .map(|a| {
+            let loaded = load(cfg, a)?;
+            total += loaded.body.len() as u64;
+            if total > cfg.max_total_size {
+                return Err(Error::new(
+                    ErrorKind::Attachment,
+                    format!("附件总大小超过 {} 字节", cfg.max_total_size),
+                    None,
+                ));
+            }
+            Ok(loaded)
+        })
+        .collect()
+}
+
+/// 读取附件
+pub fn load(cfg: &AttachmentConfig, a: &Attachment) -> Result<Loaded> {
+    let path = resolve(cfg, &a.source)?;
+    // 最多读取限制的大小加一个字节，读取过程中文件变大也不会超出限制
+    let mut body = Vec::new();
+    File::open(&path)
+        .map_err(io_error(&path))?
+        .take(cfg.max_size.saturating_add(1))
+        .read_to_end(&mut body)
+        .map_err(io_error(&path))?;
+    if body.len() as u64 > cfg.max_size {
+        return Err(Error::new(
+            ErrorKind::Attachment,
+            format!("附件 {} 的大小超过 {} 字节", path.display(), cfg.max_size),
+            None,
+        ));
+    }
+
+    let filename = a.filename.clone().unwrap_or_else(|| {
+        path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    let content_type = match &a.content_type {
+        Some(ct) => ct.clone(),
+        None => detect(&filename, &body),
+    };
+    let content_type = ContentType::parse(&content_type).map_err(|e| {
+        Error::new(
+            ErrorKind::Attachment,
+            format!("附件 {} 的类型 {} 无效", filename, content_type),
+            Some(Box::new(e)),
+        )
+    })?;
+
+    Ok(Loaded {
+        filename,
+        content_type,
+        content_id: a.content_id.clone(),
+        body,
+    })
+}
+
+/// 检测 MIME 类型：先根据内容，再根据文件扩展名
+pub fn detect(filename: &str, body: &[u8]) -> String {
+    if let Some(kind) = infer::get(body) {
+        return kind.mime_type().to_string();
+    }
+    mime_guess::from_path(filename)
+        .first_raw()
+        .unwrap_or("application/octet-stream")
+        .to_string()
+}
+
+/// 附件的实际路径，不允许引用目录之外的文件
+fn resolve(cfg: &AttachmentConfig, source: &AttachmentSource) -> Result<PathBuf> {
+    let (dir, name) = match source {
+        AttachmentSource::Path(path) => (&cfg.dir, path),
+        AttachmentSource::Blob(id) => {
+            let valid = !id.is_empty()
+                && !id.starts_with('.')
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+            if !valid {
+                return Err(Error::new(
+                    ErrorKind::Attachment,
+                    format!("附件 ID 无效：{}", id),
+                    None,
+                ));
+            }
+            (&cfg.blob_dir, id)
+        }
+    };
+
+    let dir = Path::new(dir).canonicalize().map_err(io_error(dir))?;
+    let path = dir.join(name);
+    let path = path.canonicalize().map_err(io_error(&path))?;
+    if !path.starts_with(&dir) {
+        return Err(Error::new(
+            ErrorKind::Attachment,
+            format!("附件不在目录 {} 中：{}", dir.display(), name),
+            None,
+        ));
+    }
+    Ok(path)
+}
+
+fn io_error<P: AsRef<Path>>(path: P) -> impl FnOnce(std::io::Error) -> Error {
+    let path = path.as_ref().display().to_string();
+    move |e| {
+        Error::new(
+            ErrorKind::Attachment,
+            format!("无法读取附件 {}：{}", path, e),
+            Some(Box::new(e)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lettre::message::header::ContentType;
+
+    use crate::{model::email::Attachment, AttachmentConfig, ErrorKind};
+
+    const PNG: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, 0x49, 0x48, 0x44, 0x52,
+    ];
+
+    fn config() -> AttachmentConfig {
+        let root = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let dir = root.join("attachments");
+        let blob_dir = root.join("blobs");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(&blob_dir).unwrap();
+        std::fs::write(dir.join("logo"), PNG).unwrap();
+        std::fs::write(dir.join("readme.txt"), "hello").unwrap();
+        std::fs::write(blob_dir.join("b-1"), b"%PDF-1.4\n").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret").unwrap();
+        AttachmentConfig {
+            dir: dir.to_string_lossy().to_string(),
+            blob_dir: blob_dir.to_string_lossy().to_string(),
+            max_size: 1024,
+            max_total_size: 1024,
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(super::detect("logo", PNG), "image/png");
+        assert_eq!(super::detect("a.txt", b"hello"), "text/plain");
+        assert_eq!(super::detect("a.csv", b"a,b"), "text/csv");
+        assert_eq!(super::detect("a", b"hello"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_load() {
+        let cfg = config();
+        let logo = super::load(&cfg, &Attachment::path("logo").inline("logo")).unwrap();
+        assert_eq!(logo.filename, "logo");
+        assert_eq!(logo.content_type, ContentType::parse("image/png").unwrap());
+        assert_eq!(logo.content_id.as_deref(), Some("logo"));
+
+        let blob = super::load(&cfg, &Attachment::blob("b-1").filename("报告.pdf")).unwrap();
+        assert_eq!(blob.filename, "报告.pdf");
+        assert_eq!(
+            blob.content_type,
+            ContentType::parse("application/pdf").unwrap()
+        );
+
+        let text = super::load(
+            &cfg,
+            &Attachment::path("readme.txt").content_type("text/markdown"),
+        )
+        .unwrap();
+        assert_eq!(
+            text.content_type,
+            ContentType::parse("text/markdown").unwrap()
+        );
+
+        for a in [
+            Attachment::path("../secret.txt"),
+            Attachment::path("missing.txt"),
+            Attachment::blob("../attachments/logo"),
+            Attachment::path("readme.txt").content_type("not a type"),
+        ] {
+            let err = super::load(&cfg, &a).err().unwrap();
+            assert!(matches!(err.kind, ErrorKind::Attachment), "{:?}", a);
+        }
+        std::fs::remove_dir_all(std::path::Path::new(&cfg.dir).parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let cfg = AttachmentConfig {
+            max_size: 10,
+            ..config()
+        };
+        assert!(super::load(&cfg, &Attachment::path("readme.txt")).is_ok());
+        assert!(super::load(&cfg, &Attachment::path("logo")).is_err());
+
+        let cfg = AttachmentConfig {
+            max_total_size: 8,
+            ..cfg
+        };
+        let both = [
+            Attachment::path("readme.txt"),
+            Attachment::path("readme.txt"),
+        ];
+        assert!(super::load_all(&cfg, &both[..1]).is_ok());
+        let err = super::load_all(&cfg, &both).err().unwrap();
+        assert!(err.message.contains("总大小"));
+        std::fs::remove_dir_all(std::path::Path::new(&cfg.dir).parent().unwrap()).ok();
+    }
+}
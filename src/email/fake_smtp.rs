@@ -0,0 +1,134 @@
+//! 测试用的 SMTP 服务器
+//!
+//! 只实现发送邮件所需的最少命令，收到的邮件保存在内存中。
+
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{EmailConfig, EmailTransportKind};
+
+/// 收到的邮件
+#[derive(Debug, Clone, Default)]
+pub struct Received {
+    pub from: String,
+    pub to: Vec<String>,
+    pub data: String,
+}
+
+pub struct FakeSmtp {
+    pub port: u16,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl FakeSmtp {
+    /// 在随机端口上启动
+    pub async fn start() -> Self {
+        Self::start_with_rcpt_reply("250 OK").await
+    }
+
+    /// 启动，并以 `reply` 回复所有 `RCPT TO` 命令，用于模拟收件人被拒收等错误
+    pub async fn start_with_rcpt_reply(reply: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let store = received.clone();
+        let reply = reply.to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(session(stream, store.clone(), reply.clone()));
+            }
+        });
+
+        Self { port, received }
+    }
+
+    /// 连接到该服务器的配置
+    pub fn config(&self) -> EmailConfig {
+        EmailConfig {
+            transport: EmailTransportKind::Smtp,
+            host: "127.0.0.1".to_string(),
+            port: self.port,
+            tls: false,
+            ..Default::default()
+        }
+    }
+
+    /// 已收到的邮件
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn session(stream: TcpStream, store: Arc<Mutex<Vec<Received>>>, rcpt_reply: String) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut current = Received::default();
+
+    macro_rules! reply {
+        ($s:expr) => {
+            if writer
+                .write_all(format!("{}\r\n", $s).as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        };
+    }
+
+    reply!("220 localhost ESMTP fake");
+    while let Ok(Some(line)) = lines.next_line().await {
+        let upper = line.to_ascii_uppercase();
+        if upper.starts_with("EHLO") || upper.starts_with("HELO") {
+            reply!("250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME");
+        } else if upper.starts_with("AUTH") {
+            reply!("235 2.7.0 Authentication successful");
+        } else if upper.starts_with("MAIL FROM:") {
+            current = Received {
+                from: address(&line[10..]),
+                ..Default::default()
+            };
+            reply!("250 OK");
+        } else if upper.starts_with("RCPT TO:") {
+            if rcpt_reply.starts_with('2') {
+                current.to.push(address(&line[8..]));
+            }
+            reply!(rcpt_reply);
+        } else if upper == "DATA" {
+            reply!("354 End data with <CR><LF>.<CR><LF>");
+            let mut data = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                    break;
+                }
+                // 去掉透明处理时加上的点
+                data.push(line.strip_prefix('.').unwrap_or(&line).to_string());
+            }
+            current.data = data.join("\r\n");
+            store.lock().unwrap().push(std::mem::take(&mut current));
+            reply!("250 OK: queued");
+        } else if upper == "QUIT" {
+            reply!("221 Bye");
+            return;
+        } else if upper == "RSET" || upper == "NOOP" {
+            reply!("250 OK");
+        } else {
+            reply!("502 Command not implemented");
+        }
+    }
+}
+
+/// 从 `<user@example.com> SIZE=123` 中取出邮箱地址
+fn address(s: &str) -> String {
+    let s = s.trim();
+    let end = s.find('>').map(|i| i + 1).unwrap_or(s.len());
+    s[..end]
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+}
@@ -0,0 +1,297 @@
+use std::sync::{Arc, Mutex};
+
+use lettre::{
+    address::Envelope, transport::smtp::authentication::Credentials, AsyncFileTransport,
+    AsyncSmtpTransport, AsyncTransport, FileTransport, SmtpTransport, Tokio1Executor, Transport,
+};
+
+use crate::{model, AttachmentConfig, EmailConfig, EmailTransportKind, Error, ErrorKind, Result};
+
+pub mod attachment;
+pub mod dkim;
+#[cfg(test)]
+pub(crate) mod fake_smtp;
+
+/// 通过内存发送的邮件
+#[derive(Debug, Clone)]
+pub struct Sent {
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    /// 完整的邮件内容
+    pub raw: String,
+}
+
+impl Sent {
+    fn new(envelope: &Envelope, raw: &[u8]) -> Self {
+        Self {
+            from: envelope.from().map(|a| a.to_string()),
+            to: envelope.to().iter().map(|a| a.to_string()).collect(),
+            raw: String::from_utf8_lossy(raw).to_string(),
+        }
+    }
+}
+
+enum Inner {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    File(AsyncFileTransport<Tokio1Executor>),
+    Memory(Arc<Mutex<Vec<Sent>>>),
+}
+
+/// 发件人
+///
+/// 根据 [`EmailConfig::transport`] 选择发送方式，创建后可以重复使用。
+pub struct Mailer {
+    inner: Inner,
+    attachment: AttachmentConfig,
+    dkim: Option<dkim::Signer>,
+}
+
+impl Mailer {
+    pub fn new(cfg: &EmailConfig) -> Result<Self> {
+        let inner = match cfg.transport {
+            EmailTransportKind::Smtp => Inner::Smtp(async_smtp(cfg)?),
+            EmailTransportKind::File => {
+                create_dir(cfg)?;
+                Inner::File(AsyncFileTransport::<Tokio1Executor>::new(&cfg.file_dir))
+            }
+            EmailTransportKind::Memory => Inner::Memory(Default::default()),
+        };
+        Ok(Self {
+            inner,
+            attachment: cfg.attachment.clone(),
+            dkim: dkim::Signer::new(&cfg.dkim)?,
+        })
+    }
+
+    /// 异步发送
+    pub async fn send(&self, m: &model::email::Email) -> Result<()> {
+        // 读取附件会阻塞，在阻塞线程中生成邮件
+        let (email, cfg) = (m.clone(), self.attachment.clone());
+        let message = tokio::task::spawn_blocking(move || email.to_message(&cfg))
+            .await
+            .map_err(|e| Error::with_cause(ErrorKind::Attachment, Box::new(e)))??;
+        let raw = format(&message, self.dkim.as_ref())?;
+        let envelope = message.envelope();
+        match &self.inner {
+            Inner::Smtp(mailer) => {
+                let resp = mailer.send_raw(envelope, &raw).await.map_err(Error::from)?;
+                tracing::debug!("SMTP 服务器的响应：{:?}", resp);
+            }
+            Inner::File(mailer) => {
+                let id = mailer.send_raw(envelope, &raw).await.map_err(Error::from)?;
+                tracing::debug!("邮件已保存：{}", id);
+            }
+            Inner::Memory(sent) => {
+                sent.lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(Sent::new(envelope, &raw));
+            }
+        }
+        Ok(())
+    }
+
+    /// 通过内存发送的邮件，其他发送方式时为空
+    pub fn sent(&self) -> Vec<Sent> {
+        match &self.inner {
+            Inner::Memory(sent) => sent.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            _ => vec![],
+        }
+    }
+}
+
+/// 同步发送
+///
+/// 每次发送都会重新建立连接。不支持内存发送，内存发送的邮件由 [`Mailer::sent`] 读取。
+pub fn sync_send(cfg: &EmailConfig, m: &model::email::Email) -> Result<()> {
+    let message = m.to_message(&cfg.attachment)?;
+    let raw = format(&message, dkim::Signer::new(&cfg.dkim)?.as_ref())?;
+    let envelope = message.envelope();
+
+    match cfg.transport {
+        EmailTransportKind::Smtp => {
+            let resp = sync_smtp(cfg)?
+                .send_raw(envelope, &raw)
+                .map_err(Error::from)?;
+            tracing::debug!("SMTP 服务器的响应：{:?}", resp);
+        }
+        EmailTransportKind::File => {
+            create_dir(cfg)?;
+            FileTransport::new(&cfg.file_dir)
+                .send_raw(envelope, &raw)
+                .map_err(Error::from)?;
+        }
+        EmailTransportKind::Memory => {
+            return Err(Error::from_str(
+                ErrorKind::Email,
+                "同步发送不支持内存发送方式，请使用 Mailer",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// 异步发送
+pub async fn send(cfg: EmailConfig, m: model::email::Email) -> Result<()> {
+    Mailer::new(&cfg)?.send(&m).await
+}
+
+/// 生成完整的邮件，配置了 DKIM 时签名
+fn format(message: &lettre::Message, dkim: Option<&dkim::Signer>) -> Result<Vec<u8>> {
+    match dkim {
+        Some(signer) => signer.sign(message),
+        None => Ok(message.formatted()),
+    }
+}
+
+fn credentials(cfg: &EmailConfig) -> Option<Credentials> {
+    if cfg.username.is_empty() {
+        None
+    } else {
+        Some(Credentials::new(cfg.username.clone(), cfg.password.clone()))
+    }
+}
+
+fn async_smtp(cfg: &EmailConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+    let mut builder = if cfg.tls {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.host).map_err(Error::from)?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.host)
+    };
+    if cfg.port != 0 {
+        builder = builder.port(cfg.port);
+    }
+    if let Some(creds) = credentials(cfg) {
+        builder = builder.credentials(creds);
+    }
+    Ok(builder.build())
+}
+
+fn sync_smtp(cfg: &EmailConfig) -> Result<SmtpTransport> {
+    let mut builder = if cfg.tls {
+        SmtpTransport::relay(&cfg.host).map_err(Error::from)?
+    } else {
+        SmtpTransport::builder_dangerous(&cfg.host)
+    };
+    if cfg.port != 0 {
+        builder = builder.port(cfg.port);
+    }
+    if let Some(creds) = credentials(cfg) {
+        builder = builder.credentials(creds);
+    }
+    Ok(builder.build())
+}
+
+fn create_dir(cfg: &EmailConfig) -> Result<()> {
+    std::fs::create_dir_all(&cfg.file_dir)
+        .map_err(|e| Error::with_cause(ErrorKind::Email, Box::new(e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::fake_smtp::FakeSmtp;
+    use crate::{model, EmailConfig, EmailTransportKind, SmtpClass};
+
+    fn email(subject: &str, body: &str) -> model::email::Email {
+        model::email::Email {
+            from: "noreply@axum.rs".to_string(),
+            to: vec!["team@axum.rs".to_string()],
+            subject: subject.to_string(),
+            body: body.to_string(),
+            html: None,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sync_send_email() {
+        let server = FakeSmtp::start().await;
+        let cfg = server.config();
+        let m = email("试试同步发送", "你好呀，这是用lettre同步发送的邮件！");
+        tokio::task::spawn_blocking(move || super::sync_send(&cfg, &m))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let received = server.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].from, "noreply@axum.rs");
+        assert_eq!(received[0].to, vec!["team@axum.rs".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_async_send_email() {
+        let server = FakeSmtp::start().await;
+        let m = email("试试异步发送", "你好呀，这是用lettre异步发送的邮件！");
+        super::send(server.config(), m).await.unwrap();
+
+        let received = server.received();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].data.contains("Subject:"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_recipient() {
+        let server = FakeSmtp::start_with_rcpt_reply("550 5.1.1 No such user").await;
+        let err = super::send(server.config(), email("hi", "hi"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.smtp_class(), Some(SmtpClass::Recipient));
+        assert_eq!(err.smtp_code(), Some(550));
+        assert!(server.received().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_file_transport() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let cfg = EmailConfig {
+            transport: EmailTransportKind::File,
+            file_dir: dir.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+        super::send(cfg.clone(), email("async", "async"))
+            .await
+            .unwrap();
+        super::sync_send(&cfg, &email("sync", "sync")).unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 2);
+        assert!(files
+            .iter()
+            .all(|f| f.extension().and_then(|e| e.to_str()) == Some("eml")));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_memory_transport() {
+        let cfg = EmailConfig {
+            transport: EmailTransportKind::Memory,
+            ..Default::default()
+        };
+        let mailer = super::Mailer::new(&cfg).unwrap();
+        mailer.send(&email("你好", "内容")).await.unwrap();
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].from.as_deref(), Some("noreply@axum.rs"));
+        assert_eq!(sent[0].to, vec!["team@axum.rs".to_string()]);
+        assert!(sent[0].raw.contains("Subject:"));
+
+        assert!(super::sync_send(&cfg, &email("你好", "内容")).is_err());
+    }
+
+    #[test]
+    fn test_credentials_not_serialized() {
+        let cfg = EmailConfig {
+            username: "team@axum.rs".to_string(),
+            password: "secret".to_string(),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&cfg).unwrap();
+        assert!(json.contains("team@axum.rs"));
+        assert!(!json.contains("password"));
+        assert!(!json.contains("secret"));
+    }
+}
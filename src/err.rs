@@ -1,22 +1,59 @@
-use axum::response::IntoResponse;
+use axum::{http::StatusCode, response::IntoResponse};
 
 #[derive(Debug)]
 pub enum Kind {
     Config,
     RabbitMQ,
     Email,
+    /// 发送邮件时的 SMTP 错误，`code` 为服务器返回的状态码
+    Smtp {
+        code: Option<u16>,
+        class: SmtpClass,
+    },
     Serde,
+    Redis,
+    Activation,
+    Outbox,
+    Message,
+    Template,
+    Attachment,
+}
+
+/// SMTP 错误的分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpClass {
+    /// 4xx 状态码或连接错误，可以稍后重试
+    Transient,
+    /// 收件人不存在或被拒收，不应再向该地址发送
+    Recipient,
+    /// 其他 5xx 状态码或客户端错误，重试也不会成功
+    Permanent,
+}
+
+impl SmtpClass {
+    /// 根据状态码分类，没有状态码时视为连接错误
+    pub fn from_code(code: Option<u16>) -> Self {
+        match code {
+            Some(550 | 551 | 553) => Self::Recipient,
+            Some(500..=599) => Self::Permanent,
+            _ => Self::Transient,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Error {
     pub kind: Kind,
     pub message: String,
-    pub cause: Option<Box<dyn std::error::Error>>,
+    pub cause: Option<Box<dyn std::error::Error + Send + Sync>>,
 }
 
 impl Error {
-    pub fn new(kind: Kind, message: String, cause: Option<Box<dyn std::error::Error>>) -> Self {
+    pub fn new(
+        kind: Kind,
+        message: String,
+        cause: Option<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
         Self {
             kind,
             message,
@@ -28,9 +65,30 @@ impl Error {
         Self::new(kind, msg.to_string(), None)
     }
 
-    pub fn with_cause(kind: Kind, cause: Box<dyn std::error::Error>) -> Self {
+    pub fn with_cause(kind: Kind, cause: Box<dyn std::error::Error + Send + Sync>) -> Self {
         Self::new(kind, cause.to_string(), Some(cause))
     }
+
+    /// SMTP 错误的分类
+    pub fn smtp_class(&self) -> Option<SmtpClass> {
+        match self.kind {
+            Kind::Smtp { class, .. } => Some(class),
+            _ => None,
+        }
+    }
+
+    /// SMTP 服务器返回的状态码
+    pub fn smtp_code(&self) -> Option<u16> {
+        match self.kind {
+            Kind::Smtp { code, .. } => code,
+            _ => None,
+        }
+    }
+
+    /// 是否为可以重试的错误
+    pub fn is_transient(&self) -> bool {
+        self.smtp_class() == Some(SmtpClass::Transient)
+    }
 }
 
 impl std::error::Error for Error {}
@@ -61,18 +119,75 @@ impl From<lettre::error::Error> for Error {
 
 impl From<lettre::transport::smtp::Error> for Error {
     fn from(e: lettre::transport::smtp::Error) -> Self {
+        let code = e.status().and_then(|c| c.to_string().parse().ok());
+        let class = if e.is_client() {
+            SmtpClass::Permanent
+        } else {
+            SmtpClass::from_code(code)
+        };
+        Self::with_cause(Kind::Smtp { code, class }, Box::new(e))
+    }
+}
+
+impl From<lettre::transport::file::Error> for Error {
+    fn from(e: lettre::transport::file::Error) -> Self {
         Self::with_cause(Kind::Email, Box::new(e))
     }
 }
 
+impl From<tera::Error> for Error {
+    fn from(e: tera::Error) -> Self {
+        // 模板错误的原因在 `source` 中，一并记录下来
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(s) = source {
+            message.push_str(&format!("：{}", s));
+            source = s.source();
+        }
+        Self::new(Kind::Template, message, Some(Box::new(e)))
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
         Self::with_cause(Kind::Serde, Box::new(e))
     }
 }
 
+impl From<redis::RedisError> for Error {
+    fn from(e: redis::RedisError) -> Self {
+        Self::with_cause(Kind::Redis, Box::new(e))
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::with_cause(Kind::Outbox, Box::new(e))
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        self.message.into_response()
+        let status = match self.kind {
+            // 消息队列不可用
+            Kind::RabbitMQ | Kind::Redis => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.message).into_response()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SmtpClass;
+
+    #[test]
+    fn test_smtp_class() {
+        assert_eq!(SmtpClass::from_code(Some(421)), SmtpClass::Transient);
+        assert_eq!(SmtpClass::from_code(Some(452)), SmtpClass::Transient);
+        assert_eq!(SmtpClass::from_code(None), SmtpClass::Transient);
+        assert_eq!(SmtpClass::from_code(Some(550)), SmtpClass::Recipient);
+        assert_eq!(SmtpClass::from_code(Some(553)), SmtpClass::Recipient);
+        assert_eq!(SmtpClass::from_code(Some(554)), SmtpClass::Permanent);
     }
 }
@@ -4,10 +4,22 @@ use serde::Deserialize;
 pub struct RegisterForm {
     pub username: String,
     pub email: String,
+    /// 页面的语言，为空时根据 `Accept-Language` 确定
+    #[serde(default)]
+    pub locale: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct ActiveForm {
     pub code: String,
     pub email: String,
+    /// 页面的语言，为空时根据 `Accept-Language` 确定
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+/// 页面地址中的语言参数，如 `?lang=en`
+#[derive(Deserialize, Default)]
+pub struct LocaleQuery {
+    pub lang: Option<String>,
 }
@@ -1,171 +1,222 @@
 use std::sync::Arc;
 
 use axum::{
-    http::{HeaderMap, StatusCode},
+    extract::{Path, Query},
+    http::{header::ACCEPT_LANGUAGE, HeaderMap, StatusCode},
     response::Html,
-    Extension, Form,
+    Extension, Form, Json,
 };
+use serde_json::{json, Value};
 
 use crate::{
-    form,
+    activation, form, i18n,
+    message::Envelope,
     model::{self, state::AppState},
-    rabbitmq::topic,
-    Error, Result,
+    rabbitmq::handler::Publish,
+    Result,
 };
 
-/// 生成激活码
-fn gen_active_code(_email: &str) -> String {
-    // 仅作演示！这里只返回固定字符
-    // 正式开发时，应该：
-    // 1. 随机字符
-    // 2. 与邮箱关联，存储到 redis 中
-    return format!("axum.rs");
-}
-
-/// 获取激活码
-fn get_active_code(_email: &str) -> String {
-    // 仅作演示！这里只返回固定字符
-    // 正式开发时，应该：从 redis 中通过邮箱地址取出
-    return format!("axum.rs");
+/// 生成激活码，并与邮箱关联保存
+async fn gen_active_code(state: &AppState, email: &str) -> Result<String> {
+    activation::issue(state.store.as_ref(), &state.cfg.activation, email).await
 }
 
 pub async fn register(
     Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
     Form(frm): Form<form::RegisterForm>,
 ) -> Result<(StatusCode, HeaderMap, ())> {
     let cfg = &state.cfg;
+    let locale = request_locale(frm.locale.as_deref(), &headers);
+    let link = activation::link::sign(&cfg.activation, &frm.email)?
+        .map(|token| format!("{}/active/{}?lang={}", cfg.web.base_url(), token, locale));
     let active_code = model::user::ActiveCode {
-        code: gen_active_code(&frm.email),
+        code: gen_active_code(&state, &frm.email).await?,
         email: frm.email,
-        email_cfg: cfg.email.clone(),
+        link,
+        locale: Some(locale.to_string()),
     };
 
-    let payload = serde_json::to_string(&active_code).map_err(Error::from)?;
+    // 以请求 ID 作为关联 ID，便于追踪
+    let correlation_id = headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let envelope = Envelope::new(active_code).with_correlation_id(correlation_id);
+
+    // 发送消息。使用发件箱时，由后台负责发送
+    let routing_key = &cfg.rabbitmq.routing_key;
+    match &state.outbox {
+        Some(outbox) => outbox.publish(routing_key, envelope).await?,
+        None => Publish::publish(state.broker.as_ref(), routing_key, envelope).await?,
+    }
+
+    redirect(&format!("/active?lang={}", locale))
+}
 
-    // 发送消息
-    topic::send(
-        &cfg.rabbitmq.dsn,
-        &cfg.rabbitmq.exchange_name,
-        &cfg.rabbitmq.queue_name,
-        &cfg.rabbitmq.routing_key,
-        &payload,
+pub async fn active(
+    Extension(state): Extension<Arc<AppState>>,
+    headers: HeaderMap,
+    Form(frm): Form<form::ActiveForm>,
+) -> Result<Html<String>> {
+    let locale = request_locale(frm.locale.as_deref(), &headers);
+    let outcome = activation::verify(
+        state.store.as_ref(),
+        &state.cfg.activation,
+        &frm.email,
+        &frm.code,
     )
-    .await
-    .map_err(Error::from)?;
+    .await?;
+
+    active_done_ui(outcome, locale)
+}
 
-    redirect("/active")
+/// 通过激活链接激活
+pub async fn active_link(
+    Extension(state): Extension<Arc<AppState>>,
+    Path(token): Path<String>,
+    Query(query): Query<form::LocaleQuery>,
+    headers: HeaderMap,
+) -> Result<Html<String>> {
+    let locale = request_locale(query.lang.as_deref(), &headers);
+    let outcome =
+        activation::verify_link(state.store.as_ref(), &state.cfg.activation, &token).await?;
+
+    active_done_ui(outcome, locale)
 }
 
-pub async fn active(Form(frm): Form<form::ActiveForm>) -> Result<Html<String>> {
-    let code = get_active_code(&frm.email);
-    let is_ok = frm.code == code;
+/// 健康检查
+pub async fn health(Extension(state): Extension<Arc<AppState>>) -> Json<Value> {
+    let consumer = state.consumer_state.as_ref().map(|s| *s.borrow());
+    Json(json!({ "consumer": consumer }))
+}
 
-    active_done_ui(is_ok)
+/// 确定页面的语言：优先使用明确指定的，否则根据 `Accept-Language` 协商
+fn request_locale(explicit: Option<&str>, headers: &HeaderMap) -> &'static str {
+    explicit.and_then(i18n::resolve).unwrap_or_else(|| {
+        i18n::negotiate(
+            headers
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default(),
+        )
+    })
 }
 
-pub async fn register_ui() -> Result<Html<String>> {
-    let html = r#"<!DOCTYPE html>
-<html lang="en">
+/// 页面的框架
+fn page(locale: &str, title: &str, body: &str) -> Html<String> {
+    let switch = i18n::locales()
+        .map(|l| {
+            format!(
+                r#"<a href="?lang={}" class="underline">{}</a>"#,
+                l,
+                i18n::t(l, "language_name")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" | ");
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="{locale}">
 <head>
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
     <script src="https://cdn.tailwindcss.com?plugins=forms"></script>
-    <title>用户注册</title>
+    <title>{title}</title>
 </head>
 <body>
     <div class="max-w-xs mx-auto my-6">
-        <h1 class="text-lg font-bold my-3">用户注册</h1>
-        <form action="/register" method="post">
+        <div class="text-sm text-gray-500">{switch}</div>
+        <h1 class="text-lg font-bold my-3">{title}</h1>
+{body}
+    </div>
+</body>
+</html>"#,
+    ))
+}
+
+pub async fn register_ui(
+    Query(query): Query<form::LocaleQuery>,
+    headers: HeaderMap,
+) -> Result<Html<String>> {
+    let locale = request_locale(query.lang.as_deref(), &headers);
+    let t = i18n::catalog(locale);
+    let body = format!(
+        r#"        <form action="/register" method="post">
+            <input type="hidden" name="locale" value="{locale}" />
             <div class="grid grid-cols-1 gap-6">
                 <label class="block">
-                    <span class="text-gray-700">用户名</span>
-                    <input type="text" name="username" class="mt-1 block w-full" placeholder="请输入你的用户名" required />
+                    <span class="text-gray-700">{}</span>
+                    <input type="text" name="username" class="mt-1 block w-full" placeholder="{}" required />
                 </label>
                 <label class="block">
-                    <span class="text-gray-700">邮箱</span>
-                    <input type="email" name="email" class="mt-1 block w-full" placeholder="请输入你的邮箱" required />
+                    <span class="text-gray-700">{}</span>
+                    <input type="email" name="email" class="mt-1 block w-full" placeholder="{}" required />
                 </label>
             </div>
-           
+
             <div class="my-6">
-                <button class="border px-3 py-1 bg-blue-600 text-white text-lg">注册</button>
+                <button class="border px-3 py-1 bg-blue-600 text-white text-lg">{}</button>
             </div>
-        </form>
-    </div>
-</body>
-</html>"#;
+        </form>"#,
+        t["username"],
+        t["username_placeholder"],
+        t["email"],
+        t["email_placeholder"],
+        t["register_button"],
+    );
 
-    Ok(Html(html.to_string()))
+    Ok(page(locale, &t["register_title"], &body))
 }
 
-pub async fn active_ui() -> Result<Html<String>> {
-    let html = r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <script src="https://cdn.tailwindcss.com?plugins=forms"></script>
-    <title>激活账号</title>
-</head>
-<body>
-    <div class="max-w-xs mx-auto my-6">
-        <h1 class="text-lg font-bold my-3">激活账号</h1>
-        <form action="/active" method="post">
+pub async fn active_ui(
+    Query(query): Query<form::LocaleQuery>,
+    headers: HeaderMap,
+) -> Result<Html<String>> {
+    let locale = request_locale(query.lang.as_deref(), &headers);
+    let t = i18n::catalog(locale);
+    let body = format!(
+        r#"        <form action="/active" method="post">
+            <input type="hidden" name="locale" value="{locale}" />
             <div class="grid grid-cols-1 gap-6">
-                
                 <label class="block">
-                    <span class="text-gray-700">邮箱</span>
-                    <input type="email" name="email" class="mt-1 block w-full" placeholder="请输入你的邮箱" required />
+                    <span class="text-gray-700">{}</span>
+                    <input type="email" name="email" class="mt-1 block w-full" placeholder="{}" required />
                 </label>
                 <label class="block">
-                    <span class="text-gray-700">激活码</span>
-                    <input type="text" name="code" class="mt-1 block w-full" placeholder="请输入你的激活码" required />
+                    <span class="text-gray-700">{}</span>
+                    <input type="text" name="code" class="mt-1 block w-full" placeholder="{}" required />
                 </label>
             </div>
-           
+
             <div class="my-6">
-                <button class="border px-3 py-1 bg-blue-600 text-white text-lg">激活</button>
+                <button class="border px-3 py-1 bg-blue-600 text-white text-lg">{}</button>
             </div>
-        </form>
-    </div>
-</body>
-</html>"#;
+        </form>"#,
+        t["email"], t["email_placeholder"], t["code"], t["code_placeholder"], t["active_button"],
+    );
 
-    Ok(Html(html.to_string()))
+    Ok(page(locale, &t["active_title"], &body))
 }
 
-fn active_done_ui(is_ok: bool) -> Result<Html<String>> {
-    let text_color = if is_ok {
-        "text-green-600"
-    } else {
-        "text-red-600"
+fn active_done_ui(outcome: activation::Outcome, locale: &str) -> Result<Html<String>> {
+    let text_color = match outcome {
+        activation::Outcome::Success => "text-green-600",
+        _ => "text-red-600",
     };
-    let msg = if is_ok {
-        "你的账号已成功激活"
-    } else {
-        "激活码错误，请检查你的邮箱"
+    let key = match outcome {
+        activation::Outcome::Success => "outcome_success",
+        activation::Outcome::Expired => "outcome_expired",
+        activation::Outcome::WrongCode => "outcome_wrong_code",
+        activation::Outcome::TooManyAttempts => "outcome_too_many_attempts",
     };
-    let html = format!(
-        r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <script src="https://cdn.tailwindcss.com"></script>
-    <title>激活账号</title>
-</head>
-<body>
-    <div class="max-w-xs mx-auto my-6">
-        <h1 class="text-lg font-bold my-3">激活账号</h1>
-        <div class="my-3 {} text-xl">{}</div>
-       
-    </div>
-</body>
-</html>"#,
-        text_color, msg
+    let body = format!(
+        r#"        <div class="my-3 {} text-xl">{}</div>"#,
+        text_color,
+        i18n::t(locale, key)
     );
 
-    Ok(Html(html.to_string()))
+    Ok(page(locale, &i18n::t(locale, "active_title"), &body))
 }
 
 fn redirect(url: &str) -> Result<(StatusCode, HeaderMap, ())> {
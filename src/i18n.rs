@@ -0,0 +1,139 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+/// 默认语言，无法确定用户的语言或缺少翻译时使用
+pub const DEFAULT_LOCALE: &str = "zh-CN";
+
+/// 支持的语言，以及各自的语言包
+const CATALOGS: &[(&str, &str)] = &[
+    ("zh-CN", include_str!("../locales/zh-CN.json")),
+    ("en", include_str!("../locales/en.json")),
+];
+
+/// 语言包，键为文本的名称
+pub type Catalog = HashMap<String, String>;
+
+/// 支持的语言
+pub fn locales() -> impl Iterator<Item = &'static str> {
+    CATALOGS.iter().map(|(locale, _)| *locale)
+}
+
+/// 将语言标签对应到支持的语言
+///
+/// 先按完整的标签匹配，再按主语言匹配，如 `en-US` 对应 `en`，`zh` 和 `zh-TW` 对应 `zh-CN`。
+pub fn resolve(tag: &str) -> Option<&'static str> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return None;
+    }
+    if let Some(locale) = locales().find(|l| l.eq_ignore_ascii_case(tag)) {
+        return Some(locale);
+    }
+    let primary = tag.split(['-', '_']).next()?;
+    locales().find(|l| {
+        l.split('-')
+            .next()
+            .is_some_and(|p| p.eq_ignore_ascii_case(primary))
+    })
+}
+
+/// 根据 `Accept-Language` 请求头选择语言，按权重从高到低匹配
+pub fn negotiate(accept_language: &str) -> &'static str {
+    let mut tags: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (q > 0.0).then_some((tag, q))
+        })
+        .collect();
+    // 排序是稳定的，权重相同时保持原来的顺序
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter()
+        .find_map(|(tag, _)| resolve(tag))
+        .unwrap_or(DEFAULT_LOCALE)
+}
+
+/// 获取语言包，缺少的文本使用默认语言的
+pub fn catalog(locale: &str) -> &'static Catalog {
+    static MERGED: OnceLock<HashMap<&'static str, Catalog>> = OnceLock::new();
+    let merged = MERGED.get_or_init(|| {
+        let parsed: HashMap<&str, Catalog> = CATALOGS
+            .iter()
+            .map(|(locale, json)| {
+                let catalog = serde_json::from_str(json)
+                    .unwrap_or_else(|e| panic!("语言包 {} 格式错误：{}", locale, e));
+                (*locale, catalog)
+            })
+            .collect();
+        let default = &parsed[DEFAULT_LOCALE];
+        parsed
+            .iter()
+            .map(|(locale, catalog)| {
+                let mut merged = default.clone();
+                merged.extend(catalog.clone());
+                (*locale, merged)
+            })
+            .collect()
+    });
+    let locale = resolve(locale).unwrap_or(DEFAULT_LOCALE);
+    &merged[locale]
+}
+
+/// 翻译文本，找不到时返回 `key`
+pub fn t(locale: &str, key: &str) -> String {
+    catalog(locale)
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{catalog, locales, negotiate, resolve, t, DEFAULT_LOCALE};
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("zh-CN"), Some("zh-CN"));
+        assert_eq!(resolve("zh-cn"), Some("zh-CN"));
+        assert_eq!(resolve("zh"), Some("zh-CN"));
+        assert_eq!(resolve("zh_TW"), Some("zh-CN"));
+        assert_eq!(resolve("en-US"), Some("en"));
+        assert_eq!(resolve("fr"), None);
+        assert_eq!(resolve(""), None);
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("en-US,en;q=0.9,zh-CN;q=0.8"), "en");
+        assert_eq!(negotiate("fr-FR, zh;q=0.5, en;q=0.4"), "zh-CN");
+        assert_eq!(negotiate("zh;q=0.1, en"), "en");
+        assert_eq!(negotiate("en;q=0, fr"), DEFAULT_LOCALE);
+        assert_eq!(negotiate(""), DEFAULT_LOCALE);
+    }
+
+    #[test]
+    fn test_catalogs_complete() {
+        let keys: HashSet<_> = catalog(DEFAULT_LOCALE).keys().collect();
+        for locale in locales() {
+            let raw: super::Catalog = serde_json::from_str(
+                super::CATALOGS
+                    .iter()
+                    .find(|(l, _)| *l == locale)
+                    .unwrap()
+                    .1,
+            )
+            .unwrap();
+            assert_eq!(raw.keys().collect::<HashSet<_>>(), keys, "{}", locale);
+        }
+
+        assert_eq!(t("en", "register_button"), "Sign up");
+        assert_eq!(t("fr", "register_button"), "注册");
+        assert_eq!(t("en", "missing"), "missing");
+    }
+}
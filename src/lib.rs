@@ -1,13 +1,21 @@
+pub mod activation;
+pub mod broker;
 mod config;
 pub mod email;
 mod err;
 pub mod form;
 pub mod handler;
+pub mod i18n;
+pub mod message;
 pub mod model;
+pub mod outbox;
 pub mod rabbitmq;
+pub mod template;
+pub mod worker;
 
 pub use crate::config::*;
 pub use err::Error;
 pub use err::Kind as ErrorKind;
+pub use err::SmtpClass;
 
 pub type Result<T> = std::result::Result<T, crate::Error>;
@@ -1,85 +1,181 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use axum::{
     routing::{get, post},
     Extension, Router,
 };
 use axum_rabbitmq_lettre::{
-    email, handler,
-    model::{self, state::AppState},
-    rabbitmq::topic,
-    Config,
+    activation,
+    broker::{self, MessageBroker},
+    handler,
+    model::state::AppState,
+    outbox::{Outbox, Relay},
+    rabbitmq::consumer::Supervisor,
+    worker, BrokerKind, Error, ErrorKind, RabbitMQConfig, Result, ServeConfig, WorkerConfig,
 };
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use lapin::{message::DeliveryResult, options::BasicAckOptions};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Copy)]
+enum Command {
+    /// 只运行 WEB 服务
+    Serve,
+    /// 只运行发送邮件的消费者
+    Worker,
+    /// 同时运行 WEB 服务和消费者（默认）
+    All,
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<()> {
     dotenv().ok();
     tracing_subscriber::fmt().init();
 
-    let cfg = Config::from_env().unwrap();
+    let cli = Cli::parse();
+
+    let command = cli.command.unwrap_or(Command::All);
+    match command {
+        Command::Serve => {
+            let cfg = ServeConfig::from_env()?;
+            let broker = connect(&cfg.rabbitmq, command, !cfg.outbox.path.is_empty()).await?;
+            serve(cfg, broker, None).await?;
+        }
+        Command::Worker => {
+            let cfg = WorkerConfig::from_env()?;
+            let broker = connect(&cfg.rabbitmq, command, false).await?;
+            let consumer = worker::start(&cfg, broker.as_ref())?;
+            shutdown_signal().await;
+            consumer
+                .shutdown(Duration::from_secs(cfg.rabbitmq.shutdown_timeout_secs))
+                .await;
+            broker.close().await;
+        }
+        Command::All => {
+            let cfg = ServeConfig::from_env()?;
+            let worker_cfg = WorkerConfig::from_env()?;
+            // WEB 服务和消费者共用同一个消息代理
+            let broker = connect(&cfg.rabbitmq, command, !cfg.outbox.path.is_empty()).await?;
+            let consumer = worker::start(&worker_cfg, broker.as_ref())?;
+            serve(cfg, broker, Some(consumer)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 连接消息代理
+///
+/// 配置了发件箱时，消息先写入发件箱再由后台发送，因此启动时不连接 RabbitMQ，
+/// 服务器暂时不可用也不影响 WEB 服务启动。
+async fn connect(
+    cfg: &RabbitMQConfig,
+    command: Command,
+    lazy: bool,
+) -> Result<Arc<dyn MessageBroker>> {
+    if cfg.broker == BrokerKind::Memory && !matches!(command, Command::All) {
+        tracing::warn!("进程内的消息代理只能在同时运行 WEB 服务和消费者时使用");
+    }
+    broker::new_broker(cfg, lazy).await
+}
+
+/// 运行 WEB 服务，停止后一并停止 `consumer`
+async fn serve(
+    cfg: ServeConfig,
+    broker: Arc<dyn MessageBroker>,
+    consumer: Option<Supervisor>,
+) -> Result<()> {
+    let shutdown_timeout = Duration::from_secs(cfg.rabbitmq.shutdown_timeout_secs);
 
-    tokio::spawn(send_active_code(cfg.clone()));
+    let addr: SocketAddr = cfg.web.addr.parse().map_err(|e| {
+        Error::new(
+            ErrorKind::Config,
+            format!("WEB 地址无效：{}", cfg.web.addr),
+            Some(Box::new(e)),
+        )
+    })?;
+    let store = activation::new_store(&cfg.activation).await?;
+    let (outbox, relay) = if cfg.outbox.path.is_empty() {
+        (None, None)
+    } else {
+        let outbox = Arc::new(Outbox::open(&cfg.outbox.path).await?);
+        let relay = Relay::start(
+            outbox.clone(),
+            broker.clone(),
+            &cfg.rabbitmq.routing_key,
+            &cfg.outbox,
+        );
+        (Some(outbox), Some(relay))
+    };
 
-    let addr = cfg.web.addr.clone();
+    let state = Arc::new(AppState {
+        cfg,
+        store,
+        broker,
+        outbox,
+        consumer_state: consumer.as_ref().map(Supervisor::state),
+    });
 
     let app = Router::new()
         .route("/", get(handler::register_ui))
         .route("/register", post(handler::register))
         .route("/active", get(handler::active_ui).post(handler::active))
-        .layer(Extension(Arc::new(AppState { cfg })));
+        .route("/active/:token", get(handler::active_link))
+        .route("/health", get(handler::health))
+        .layer(Extension(state.clone()));
 
     tracing::info!("WEB运行于：{}", &addr);
 
-    axum::Server::bind(&addr.parse().unwrap())
+    let server = axum::Server::try_bind(&addr).map_err(|e| {
+        Error::new(
+            ErrorKind::Config,
+            format!("无法监听 {}：{}", addr, e),
+            Some(Box::new(e)),
+        )
+    })?;
+    if let Err(err) = server
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown_signal())
         .await
-        .unwrap();
+    {
+        tracing::error!("WEB服务异常退出：{:?}", err);
+    }
+
+    if let Some(consumer) = consumer {
+        tracing::info!("WEB已停止，等待邮件发送完成");
+        consumer.shutdown(shutdown_timeout).await;
+    }
+    if let Some(relay) = relay {
+        relay.shutdown().await;
+    }
+    state.broker.close().await;
+    Ok(())
 }
 
-async fn send_active_code(cfg: Config) {
-    topic::receive(
-        &cfg.rabbitmq.dsn,
-        &cfg.rabbitmq.exchange_name,
-        &cfg.rabbitmq.queue_name,
-        &cfg.rabbitmq.routing_key,
-        "MAIL",
-        move |delivery: DeliveryResult| async move {
-            tracing::debug!("aaa");
-            let delivery = match delivery {
-                Ok(Some(delivery)) => delivery,
-                Ok(None) => {
-                    tracing::error!("None ");
-                    return;
-                }
-                Err(err) => {
-                    tracing::error!("Failed to consume queue message {}", err);
-                    return;
-                }
-            };
-
-            let message = String::from_utf8_lossy(&delivery.data);
-            tracing::info!("Received a message: {}", message);
-
-            let ac: model::user::ActiveCode = serde_json::from_str(&message).unwrap();
-            let from = ac.email_cfg.username.clone();
-            let resp = email::send(
-                ac.email_cfg,
-                model::email::Email {
-                    from,
-                    to: ac.email,
-                    subject: format!("激活账号"),
-                    body: format!("你的激活码是：{}", ac.code),
-                },
-            )
-            .await
-            .unwrap();
-
-            tracing::info!("Send email: {:?}", resp);
-            delivery.ack(BasicAckOptions::default()).await.unwrap();
-        },
-    )
-    .await
-    .unwrap();
+/// 等待 SIGINT 或 SIGTERM 信号
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.unwrap();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("收到停止信号");
 }
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lapin::BasicProperties;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Error, ErrorKind, Result};
+
+/// 通过消息队列传递的消息
+pub trait Message: Serialize + DeserializeOwned {
+    /// 消息类型，对应 AMQP 的 `type` 属性
+    const TYPE: &'static str;
+    /// 当前的结构版本，结构不兼容时递增，并注册旧版本的升级函数
+    const VERSION: u32;
+}
+
+/// 消息信封
+///
+/// 在消息内容之外记录消息的 ID、类型、结构版本等信息，发布时同时写入 AMQP 的消息属性。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope<T> {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub version: u32,
+    /// 创建时间，Unix 时间戳（秒）
+    pub created_at: u64,
+    /// 关联 ID，用于追踪同一个请求产生的消息
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    pub payload: T,
+}
+
+/// 尚未确定具体类型的消息
+pub type RawEnvelope = Envelope<Value>;
+
+impl<T: Message> Envelope<T> {
+    pub fn new(payload: T) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: T::TYPE.to_string(),
+            version: T::VERSION,
+            created_at: now(),
+            correlation_id: None,
+            payload,
+        }
+    }
+}
+
+impl<T> Envelope<T> {
+    pub fn with_correlation_id(mut self, correlation_id: Option<String>) -> Self {
+        self.correlation_id = correlation_id;
+        self
+    }
+
+    /// 在 `base` 的基础上加入消息的 ID、类型等属性
+    pub fn properties(&self, base: BasicProperties) -> BasicProperties {
+        let mut properties = base
+            .with_message_id(self.id.as_str().into())
+            .with_kind(self.kind.as_str().into())
+            .with_content_type("application/json".into())
+            .with_timestamp(self.created_at);
+        if let Some(correlation_id) = &self.correlation_id {
+            properties = properties.with_correlation_id(correlation_id.as_str().into());
+        }
+        properties
+    }
+}
+
+impl<T: Serialize> Envelope<T> {
+    /// 序列化为消息队列的载荷
+    pub fn to_payload(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(Error::from)
+    }
+
+    /// 转换为尚未确定具体类型的消息
+    pub fn to_raw(&self) -> Result<RawEnvelope> {
+        Ok(Envelope {
+            id: self.id.clone(),
+            kind: self.kind.clone(),
+            version: self.version,
+            created_at: self.created_at,
+            correlation_id: self.correlation_id.clone(),
+            payload: serde_json::to_value(&self.payload).map_err(Error::from)?,
+        })
+    }
+}
+
+impl RawEnvelope {
+    /// 从消息队列的载荷中解析
+    ///
+    /// 没有信封的旧消息视为 `legacy_type` 类型的第 1 版，ID 使用 `message_id`（如 AMQP 的 `message_id` 属性），
+    /// 没有时根据载荷计算，保证同一条消息重新投递后 ID 不变。
+    pub fn from_payload(
+        payload: &str,
+        legacy_type: &str,
+        message_id: Option<&str>,
+    ) -> Result<Self> {
+        let value: Value = serde_json::from_str(payload).map_err(Error::from)?;
+        let enveloped = ["id", "type", "version", "payload"]
+            .iter()
+            .all(|key| value.get(key).is_some());
+        if enveloped {
+            return serde_json::from_value(value).map_err(Error::from);
+        }
+        let id = match message_id.filter(|id| !id.is_empty()) {
+            Some(id) => id.to_string(),
+            None => uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, payload.as_bytes()).to_string(),
+        };
+        Ok(Self {
+            id,
+            kind: legacy_type.to_string(),
+            version: 1,
+            created_at: now(),
+            correlation_id: None,
+            payload: value,
+        })
+    }
+}
+
+/// 将第 `n` 版的消息内容升级到第 `n + 1` 版
+type Upcaster = Box<dyn Fn(Value) -> Result<Value> + Send + Sync>;
+
+/// 消息类型的注册表
+///
+/// 记录每种消息的当前版本和旧版本的升级函数，消费者据此识别消息类型并升级旧消息。
+#[derive(Default)]
+pub struct Registry {
+    versions: HashMap<String, u32>,
+    upcasters: HashMap<(String, u32), Upcaster>,
+}
+
+impl Registry {
+    /// 注册消息类型
+    pub fn register<T: Message>(&mut self) -> &mut Self {
+        self.versions.insert(T::TYPE.to_string(), T::VERSION);
+        self
+    }
+
+    /// 注册将 `kind` 类型的第 `from` 版升级到第 `from + 1` 版的函数
+    pub fn upcaster<F>(&mut self, kind: &str, from: u32, f: F) -> &mut Self
+    where
+        F: Fn(Value) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.upcasters.insert((kind.to_string(), from), Box::new(f));
+        self
+    }
+
+    /// 将消息逐版升级到当前版本
+    pub fn upcast(&self, mut raw: RawEnvelope) -> Result<RawEnvelope> {
+        let current = *self.versions.get(&raw.kind).ok_or_else(|| {
+            Error::new(
+                ErrorKind::Message,
+                format!("未知的消息类型：{}", raw.kind),
+                None,
+            )
+        })?;
+        if raw.version > current {
+            return Err(Error::new(
+                ErrorKind::Message,
+                format!(
+                    "不支持的消息版本：{} v{}，当前版本为 v{}",
+                    raw.kind, raw.version, current
+                ),
+                None,
+            ));
+        }
+        while raw.version < current {
+            let upcaster = self
+                .upcasters
+                .get(&(raw.kind.clone(), raw.version))
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::Message,
+                        format!("无法升级消息：{} v{}", raw.kind, raw.version),
+                        None,
+                    )
+                })?;
+            raw.payload = upcaster(raw.payload)?;
+            raw.version += 1;
+        }
+        Ok(raw)
+    }
+
+    /// 升级到当前版本后解析为 `T`
+    pub fn decode<T: Message>(&self, raw: RawEnvelope) -> Result<Envelope<T>> {
+        if raw.kind != T::TYPE {
+            return Err(Error::new(
+                ErrorKind::Message,
+                format!("消息类型不匹配：{}，应为 {}", raw.kind, T::TYPE),
+                None,
+            ));
+        }
+        let raw = self.upcast(raw)?;
+        Ok(Envelope {
+            payload: serde_json::from_value(raw.payload).map_err(Error::from)?,
+            id: raw.id,
+            kind: raw.kind,
+            version: raw.version,
+            created_at: raw.created_at,
+            correlation_id: raw.correlation_id,
+        })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    use super::{Envelope, Message, RawEnvelope, Registry};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Greeting {
+        name: String,
+        lang: String,
+    }
+
+    impl Message for Greeting {
+        const TYPE: &'static str = "greeting";
+        const VERSION: u32 = 3;
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::default();
+        registry
+            .register::<Greeting>()
+            .upcaster(Greeting::TYPE, 1, |v| {
+                Ok(json!({ "name": v["who"].clone() }))
+            })
+            .upcaster(Greeting::TYPE, 2, |mut v| {
+                v["lang"] = Value::from("zh-CN");
+                Ok(v)
+            });
+        registry
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let env = Envelope::new(Greeting {
+            name: "axum.rs".to_string(),
+            lang: "en".to_string(),
+        })
+        .with_correlation_id(Some("req-1".to_string()));
+        let payload = env.to_payload().unwrap();
+
+        let raw = RawEnvelope::from_payload(&payload, "legacy", None).unwrap();
+        assert_eq!(raw.kind, "greeting");
+        assert_eq!(raw.version, 3);
+        let decoded: Envelope<Greeting> = registry().decode(raw).unwrap();
+        assert_eq!(decoded, env);
+
+        let props = env.properties(Default::default());
+        assert_eq!(props.message_id().as_ref().unwrap().as_str(), env.id);
+        assert_eq!(props.kind().as_ref().unwrap().as_str(), "greeting");
+        assert_eq!(
+            props.content_type().as_ref().unwrap().as_str(),
+            "application/json"
+        );
+        assert_eq!(*props.timestamp(), Some(env.created_at));
+        assert_eq!(props.correlation_id().as_ref().unwrap().as_str(), "req-1");
+    }
+
+    #[test]
+    fn test_upcast() {
+        let raw = RawEnvelope::from_payload(r#"{"who":"axum.rs"}"#, "greeting", None).unwrap();
+        assert_eq!(raw.version, 1);
+
+        let decoded: Envelope<Greeting> = registry().decode(raw).unwrap();
+        assert_eq!(decoded.version, 3);
+        assert_eq!(
+            decoded.payload,
+            Greeting {
+                name: "axum.rs".to_string(),
+                lang: "zh-CN".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_legacy_id() {
+        let payload = r#"{"who":"axum.rs"}"#;
+        let a = RawEnvelope::from_payload(payload, "greeting", None).unwrap();
+        let b = RawEnvelope::from_payload(payload, "greeting", None).unwrap();
+        assert_eq!(a.id, b.id);
+
+        let other = RawEnvelope::from_payload(r#"{"who":"rust"}"#, "greeting", None).unwrap();
+        assert_ne!(a.id, other.id);
+
+        let raw = RawEnvelope::from_payload(payload, "greeting", Some("msg-1")).unwrap();
+        assert_eq!(raw.id, "msg-1");
+
+        // 有信封的消息使用信封中的 ID
+        let env = Envelope::new(Greeting {
+            name: "axum.rs".to_string(),
+            lang: "en".to_string(),
+        });
+        let raw = RawEnvelope::from_payload(&env.to_payload().unwrap(), "greeting", Some("msg-1"))
+            .unwrap();
+        assert_eq!(raw.id, env.id);
+    }
+
+    #[test]
+    fn test_upcast_errors() {
+        let registry = registry();
+
+        let raw = RawEnvelope::from_payload("{}", "unknown", None).unwrap();
+        assert!(registry.upcast(raw).is_err());
+
+        let mut raw = RawEnvelope::from_payload("{}", "greeting", None).unwrap();
+        raw.version = 4;
+        assert!(registry.upcast(raw).is_err());
+
+        let raw = RawEnvelope::from_payload("{}", "greeting", None).unwrap();
+        let mut registry = Registry::default();
+        registry.register::<Greeting>();
+        assert!(registry.upcast(raw).is_err());
+    }
+}
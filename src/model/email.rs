@@ -1,24 +1,706 @@
-use lettre::{message::header::ContentType, Message};
-use serde::Deserialize;
+use std::collections::BTreeMap;
 
-use crate::{Error, Result};
+use lettre::{
+    message::{
+        header::{ContentType, Header, HeaderName, HeaderValue},
+        Mailbox, MultiPart, SinglePart,
+    },
+    Message,
+};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Default, Deserialize)]
+use crate::{email::attachment, AttachmentConfig, Error, ErrorKind, Result};
+
+/// 附件的来源
+///
+/// 通过消息队列传递邮件时只传递引用，发送时才读取内容。
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentSource {
+    /// 附件目录中的相对路径
+    Path(String),
+    /// 附件存储中的 ID
+    Blob(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub source: AttachmentSource,
+    /// 文件名，为空时使用路径或 ID
+    #[serde(default)]
+    pub filename: Option<String>,
+    /// MIME 类型，为空时根据内容和文件名检测
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// 内嵌图片的 Content-ID，HTML 中以 `cid:{content_id}` 引用。为空时作为普通附件
+    #[serde(default)]
+    pub content_id: Option<String>,
+}
+
+impl Attachment {
+    /// 以路径引用的附件
+    pub fn path(path: &str) -> Self {
+        Self::new(AttachmentSource::Path(path.to_string()))
+    }
+
+    /// 以 ID 引用的附件
+    pub fn blob(id: &str) -> Self {
+        Self::new(AttachmentSource::Blob(id.to_string()))
+    }
+
+    fn new(source: AttachmentSource) -> Self {
+        Self {
+            source,
+            filename: None,
+            content_type: None,
+            content_id: None,
+        }
+    }
+
+    pub fn filename(mut self, filename: &str) -> Self {
+        self.filename = Some(filename.to_string());
+        self
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// 作为内嵌图片
+    pub fn inline(mut self, content_id: &str) -> Self {
+        self.content_id = Some(content_id.to_string());
+        self
+    }
+}
+
+/// 由 [`Email`] 的字段生成、不能通过 `headers` 设置的邮件头
+const RESERVED_HEADERS: &[&str] = &[
+    "From",
+    "Sender",
+    "To",
+    "Cc",
+    "Bcc",
+    "Reply-To",
+    "Subject",
+    "Date",
+    "Message-ID",
+    "MIME-Version",
+    "Content-Type",
+    "Content-Transfer-Encoding",
+];
+
+/// 邮件
+///
+/// 地址可以带显示名称，如 `AXUM中文网 <team@axum.rs>`。
+#[derive(Default, Serialize, Deserialize, Clone)]
 pub struct Email {
     pub from: String,
-    pub to: String,
+    /// 收件人，兼容只有一个地址的字符串
+    #[serde(deserialize_with = "one_or_many")]
+    pub to: Vec<String>,
+    /// 抄送
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<String>,
+    /// 密送，不会出现在邮件头中
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bcc: Vec<String>,
+    /// 回复地址
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reply_to: Vec<String>,
     pub subject: String,
+    /// 纯文本内容。设置了 `html` 时作为其纯文本版本，为空时由 `html` 自动生成
     pub body: String,
+    /// HTML 内容
+    #[serde(default)]
+    pub html: Option<String>,
+    /// 附件和内嵌图片
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    /// Message-ID，为空时根据发件人的域名生成
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// 额外的邮件头，如 `List-Unsubscribe`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
 }
 
 impl Email {
-    pub fn to_message(&self) -> Result<Message> {
-        Message::builder()
-            .from(self.from.as_str().parse().unwrap())
-            .to(self.to.as_str().parse().unwrap())
+    /// 生成邮件，附件按 `cfg` 读取
+    pub fn to_message(&self, cfg: &AttachmentConfig) -> Result<Message> {
+        let from = mailbox(&self.from)?;
+        if self.to.is_empty() {
+            return Err(Error::from_str(ErrorKind::Email, "缺少收件人"));
+        }
+        let message_id = match &self.message_id {
+            Some(id) => message_id(id)?,
+            None => format!("<{}@{}>", uuid::Uuid::new_v4(), from.email.domain()),
+        };
+
+        let mut builder = Message::builder()
+            .from(from)
             .subject(self.subject.as_str())
-            .header(ContentType::TEXT_PLAIN)
-            .body(self.body.clone())
-            .map_err(Error::from)
+            .message_id(Some(message_id));
+        for to in &self.to {
+            builder = builder.to(mailbox(to)?);
+        }
+        for cc in &self.cc {
+            builder = builder.cc(mailbox(cc)?);
+        }
+        for bcc in &self.bcc {
+            builder = builder.bcc(mailbox(bcc)?);
+        }
+        for reply_to in &self.reply_to {
+            builder = builder.reply_to(mailbox(reply_to)?);
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(RawHeader::new(name, value)?);
+        }
+
+        let loaded = attachment::load_all(cfg, &self.attachments)?;
+        let (inline, attached): (Vec<_>, Vec<_>) =
+            loaded.into_iter().partition(|a| a.content_id.is_some());
+        if !inline.is_empty() && self.html.is_none() {
+            return Err(Error::from_str(
+                ErrorKind::Attachment,
+                "内嵌图片只能用于 HTML 邮件",
+            ));
+        }
+
+        let content = match &self.html {
+            Some(html) => {
+                let text = if self.body.is_empty() {
+                    html_to_text(html)
+                } else {
+                    self.body.clone()
+                };
+                let html = if inline.is_empty() {
+                    MultiPart::alternative_plain_html(text, html.clone())
+                } else {
+                    // 内嵌图片与 HTML 放在同一个 related 中
+                    let related = inline.into_iter().fold(
+                        MultiPart::related().singlepart(SinglePart::html(html.clone())),
+                        |related, a| related.singlepart(a.into_part()),
+                    );
+                    MultiPart::alternative()
+                        .singlepart(SinglePart::plain(text))
+                        .multipart(related)
+                };
+                Some(html)
+            }
+            None => None,
+        };
+
+        if attached.is_empty() {
+            return match content {
+                Some(content) => builder.multipart(content),
+                None => builder
+                    .header(ContentType::TEXT_PLAIN)
+                    .body(self.body.clone()),
+            }
+            .map_err(Error::from);
+        }
+
+        let mixed = match content {
+            Some(content) => MultiPart::mixed().multipart(content),
+            None => MultiPart::mixed().singlepart(SinglePart::plain(self.body.clone())),
+        };
+        let mixed = attached
+            .into_iter()
+            .fold(mixed, |mixed, a| mixed.singlepart(a.into_part()));
+        builder.multipart(mixed).map_err(Error::from)
+    }
+}
+
+/// 解析邮箱地址
+fn mailbox(address: &str) -> Result<Mailbox> {
+    address.parse().map_err(|e| {
+        Error::new(
+            ErrorKind::Email,
+            format!("邮箱地址无效：{}", address),
+            Some(Box::new(e)),
+        )
+    })
+}
+
+/// 检查 Message-ID，应为 `<local@domain>`，没有尖括号时自动加上
+fn message_id(id: &str) -> Result<String> {
+    let inner = match id.strip_prefix('<') {
+        Some(rest) => rest.strip_suffix('>'),
+        None => Some(id),
+    };
+    let valid = inner.is_some_and(|inner| {
+        let allowed = !inner
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>'));
+        let parts = inner.split_once('@');
+        allowed
+            && parts.is_some_and(|(local, domain)| {
+                !local.is_empty() && !domain.is_empty() && !domain.contains('@')
+            })
+    });
+    match inner {
+        Some(inner) if valid => Ok(format!("<{}>", inner)),
+        _ => Err(Error::new(
+            ErrorKind::Email,
+            format!("Message-ID 无效：{}", id.escape_debug()),
+            None,
+        )),
+    }
+}
+
+/// 兼容只有一个地址的字符串
+fn one_or_many<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(d)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+/// 名称在运行时确定的邮件头
+#[derive(Clone)]
+struct RawHeader(HeaderValue);
+
+impl RawHeader {
+    fn new(name: &str, value: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            Error::new(
+                ErrorKind::Email,
+                format!("邮件头 {} 无效：{}", name, reason),
+                None,
+            )
+        };
+        if RESERVED_HEADERS
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(name))
+        {
+            return Err(invalid("不能覆盖该邮件头"));
+        }
+        if value.contains(['\r', '\n']) {
+            return Err(invalid("不能包含换行"));
+        }
+        let name =
+            HeaderName::new_from_ascii(name.to_string()).map_err(|_| invalid("名称不合法"))?;
+        Ok(Self(HeaderValue::new(name, value.to_string())))
+    }
+}
+
+impl Header for RawHeader {
+    // 只在按类型查找邮件头时使用，设置时使用的是 `display` 中的名称
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("X-Raw-Header")
+    }
+
+    fn parse(_: &str) -> std::result::Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Err("RawHeader 不能解析".into())
+    }
+
+    fn display(&self) -> HeaderValue {
+        self.0.clone()
+    }
+}
+
+/// 将 HTML 转换为纯文本
+///
+/// 只处理邮件中常见的标签：块级元素换行，链接在文字后附上地址，忽略样式和脚本。
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut rest = html;
+    // 正在跳过的元素，如 `style`
+    let mut skip: Option<String> = None;
+    let mut href: Option<String> = None;
+    let mut link_text = String::new();
+
+    while let Some(start) = rest.find('<') {
+        if skip.is_none() {
+            push_text(&mut out, &mut link_text, href.is_some(), &rest[..start]);
+        }
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if let Some(skipping) = &skip {
+            if closing && name == *skipping {
+                skip = None;
+            }
+            continue;
+        }
+
+        match name.as_str() {
+            "style" | "script" | "head" | "title" if !closing => skip = Some(name),
+            "br" => out.push('\n'),
+            "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => out.push_str("\n\n"),
+            "div" | "tr" | "table" | "ul" | "ol" | "hr" => out.push('\n'),
+            "li" if !closing => out.push_str("\n- "),
+            "a" if !closing => {
+                href = attr(tag, "href");
+                link_text.clear();
+            }
+            "a" => {
+                if let Some(href) = href.take() {
+                    if link_text.trim() != href {
+                        out.push_str(&format!(" ({})", href));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if skip.is_none() {
+        push_text(&mut out, &mut link_text, false, rest);
+    }
+
+    normalize(&out)
+}
+
+/// 加入文本，HTML 中连续的空白视为一个空格
+fn push_text(out: &mut String, link_text: &mut String, in_link: bool, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let mut collapsed = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            space = true;
+        } else {
+            if space {
+                collapsed.push(' ');
+                space = false;
+            }
+            collapsed.push(c);
+        }
+    }
+    if space {
+        collapsed.push(' ');
+    }
+    let decoded = decode_entities(&collapsed);
+    if in_link {
+        link_text.push_str(&decoded);
+    }
+    out.push_str(&decoded);
+}
+
+/// 去掉每行首尾的空白，最多保留一个空行
+fn normalize(text: &str) -> String {
+    let mut lines: Vec<&str> = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() && lines.last().is_none_or(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+/// 读取标签的属性，属性名须在标签开头或空白之后，`=` 前后可以有空白
+fn attr(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(name) {
+        let start = from + pos;
+        from = start + name.len();
+        let boundary = lower[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace());
+        let value = match tag[from..].trim_start().strip_prefix('=') {
+            Some(value) if boundary => value.trim_start(),
+            _ => continue,
+        };
+        let value = match value.chars().next()? {
+            q @ ('"' | '\'') => value[1..].split(q).next()?,
+            _ => value.split(|c: char| c.is_whitespace()).next()?,
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                e if e.starts_with("#x") || e.starts_with("#X") => {
+                    char::from_u32(u32::from_str_radix(&e[2..], 16).ok()?)?
+                }
+                e if e.starts_with('#') => char::from_u32(e[1..].parse().ok()?)?,
+                _ => return None,
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{html_to_text, Attachment, Email};
+    use crate::{AttachmentConfig, ErrorKind};
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<html><head><title>激活</title><style>p { color: red; }</style></head>
+<body>
+    <h1>欢迎</h1>
+    <p>你的激活码是：<b>123456</b></p>
+    <p><a href="https://axum.rs/active/abc" class="btn">立即激活</a></p>
+    <p>链接：<a href="https://axum.rs">https://axum.rs</a><br>Tom &amp; Jerry &lt;3 &#x4F60;</p>
+    <ul><li>一</li><li>二</li></ul>
+</body></html>"#;
+        assert_eq!(
+            html_to_text(html),
+            "欢迎\n\n你的激活码是：123456\n\n立即激活 (https://axum.rs/active/abc)\n\n链接：https://axum.rs\nTom & Jerry <3 你\n\n- 一\n- 二"
+        );
+
+        assert_eq!(
+            html_to_text(r#"<a data-href="x" HREF = 'https://axum.rs/y'>链接</a>"#),
+            "链接 (https://axum.rs/y)"
+        );
+        assert_eq!(html_to_text(r#"<a data-href="x">链接</a>"#), "链接");
+    }
+
+    #[test]
+    fn test_plain_message() {
+        let m = Email {
+            from: "noreply@axum.rs".to_string(),
+            to: vec!["team@axum.rs".to_string()],
+            subject: "plain".to_string(),
+            body: "hello".to_string(),
+            html: None,
+            ..Default::default()
+        };
+        let raw =
+            String::from_utf8(m.to_message(&Default::default()).unwrap().formatted()).unwrap();
+        assert!(raw.contains("Content-Type: text/plain"));
+        assert!(!raw.contains("multipart"));
+    }
+
+    #[test]
+    fn test_alternative_message() {
+        let m = Email {
+            from: "noreply@axum.rs".to_string(),
+            to: vec!["team@axum.rs".to_string()],
+            subject: "html".to_string(),
+            body: String::new(),
+            html: Some("<p>hello <b>world</b></p>".to_string()),
+            ..Default::default()
+        };
+        let raw =
+            String::from_utf8(m.to_message(&Default::default()).unwrap().formatted()).unwrap();
+        assert!(raw.contains("Content-Type: multipart/alternative"));
+        assert!(raw.contains("Content-Type: text/plain"));
+        assert!(raw.contains("Content-Type: text/html"));
+        assert!(raw.contains("hello world"));
+        assert!(raw.contains("<p>hello <b>world</b></p>"));
+
+        let m = Email {
+            body: "explicit text".to_string(),
+            ..m
+        };
+        let raw =
+            String::from_utf8(m.to_message(&Default::default()).unwrap().formatted()).unwrap();
+        assert!(raw.contains("explicit text"));
+        assert!(!raw.contains("hello world"));
+    }
+
+    #[test]
+    fn test_attachments() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("logo.png"),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A],
+        )
+        .unwrap();
+        std::fs::write(dir.join("terms.txt"), "terms").unwrap();
+        let cfg = AttachmentConfig {
+            dir: dir.to_string_lossy().to_string(),
+            ..Default::default()
+        };
+
+        let m: Email = serde_json::from_value(serde_json::json!({
+            "from": "noreply@axum.rs",
+            "to": "team@axum.rs",
+            "subject": "attachments",
+            "body": "",
+            "html": r#"<p><img src="cid:logo"></p>"#,
+            "attachments": [
+                { "source": { "path": "logo.png" }, "content_id": "logo" },
+                { "source": { "path": "terms.txt" }, "filename": "条款.txt" },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            m.attachments[0],
+            Attachment::path("logo.png").inline("logo")
+        );
+        let raw = String::from_utf8(m.to_message(&cfg).unwrap().formatted()).unwrap();
+        assert!(raw.contains("Content-Type: multipart/mixed"));
+        assert!(raw.contains("Content-Type: multipart/alternative"));
+        assert!(raw.contains("Content-Type: multipart/related"));
+        assert!(raw.contains("Content-ID: <logo>"));
+        assert!(raw.contains("Content-Type: image/png"));
+        assert!(raw.contains("Content-Disposition: attachment"));
+
+        // 内嵌图片需要 HTML 内容
+        let m = Email {
+            html: None,
+            attachments: vec![Attachment::path("logo.png").inline("logo")],
+            ..m
+        };
+        assert!(m.to_message(&cfg).is_err());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_recipients_and_headers() {
+        let m: Email = serde_json::from_value(serde_json::json!({
+            "from": "AXUM中文网 <noreply@axum.rs>",
+            "to": ["team@axum.rs", "Tom <tom@axum.rs>"],
+            "cc": ["cc@axum.rs"],
+            "bcc": ["bcc@axum.rs"],
+            "reply_to": ["support@axum.rs"],
+            "subject": "hi",
+            "body": "hi",
+            "headers": { "List-Unsubscribe": "<https://axum.rs/unsubscribe>" },
+        }))
+        .unwrap();
+        let message = m.to_message(&Default::default()).unwrap();
+        let to: Vec<_> = message
+            .envelope()
+            .to()
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(
+            to,
+            vec!["team@axum.rs", "tom@axum.rs", "cc@axum.rs", "bcc@axum.rs"]
+        );
+
+        let raw = String::from_utf8(message.formatted()).unwrap();
+        assert!(raw.contains("To: team@axum.rs, Tom <tom@axum.rs>"));
+        assert!(raw.contains("Cc: cc@axum.rs"));
+        assert!(!raw.contains("bcc@axum.rs"));
+        assert!(raw.contains("Reply-To: support@axum.rs"));
+        assert!(raw.contains("List-Unsubscribe: <https://axum.rs/unsubscribe>"));
+        assert!(raw.contains("@axum.rs>\r\n") && raw.contains("Message-ID: <"));
+
+        let m = Email {
+            message_id: Some("fixed@axum.rs".to_string()),
+            ..m
+        };
+        let raw =
+            String::from_utf8(m.to_message(&Default::default()).unwrap().formatted()).unwrap();
+        assert!(raw.contains("Message-ID: <fixed@axum.rs>"));
+    }
+
+    #[test]
+    fn test_invalid_email() {
+        let valid = || Email {
+            from: "noreply@axum.rs".to_string(),
+            to: vec!["team@axum.rs".to_string()],
+            subject: "hi".to_string(),
+            body: "hi".to_string(),
+            ..Default::default()
+        };
+        assert!(valid().to_message(&Default::default()).is_ok());
+        assert_eq!(super::message_id("<a.b@axum.rs>").unwrap(), "<a.b@axum.rs>");
+
+        let invalid = [
+            Email {
+                from: "not an address".to_string(),
+                ..valid()
+            },
+            Email {
+                to: vec![],
+                ..valid()
+            },
+            Email {
+                cc: vec!["team@".to_string()],
+                ..valid()
+            },
+            Email {
+                headers: [("Subject".to_string(), "x".to_string())].into(),
+                ..valid()
+            },
+            Email {
+                headers: [("X-Bad Name".to_string(), "x".to_string())].into(),
+                ..valid()
+            },
+            Email {
+                headers: [("X-Tag".to_string(), "a\r\nBcc: x@axum.rs".to_string())].into(),
+                ..valid()
+            },
+        ];
+        let invalid = invalid.into_iter().chain(
+            [
+                "<a@axum.rs>\r\nBcc: x@axum.rs",
+                "<a@axum.rs",
+                "<<a@axum.rs>>",
+                "<a@axum.rs><b@axum.rs>",
+                "a@b@axum.rs",
+                "axum.rs",
+                "<@axum.rs>",
+                "a @axum.rs",
+            ]
+            .map(|id| Email {
+                message_id: Some(id.to_string()),
+                ..valid()
+            }),
+        );
+        for m in invalid {
+            let err = m.to_message(&Default::default()).err().unwrap();
+            assert!(matches!(err.kind, ErrorKind::Email), "{}", err.message);
+        }
     }
 }
@@ -1,5 +1,18 @@
-use crate::Config;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use crate::{
+    activation::ActivationCodeStore, broker::MessageBroker, outbox::Outbox,
+    rabbitmq::consumer::ConsumerState, ServeConfig,
+};
 
 pub struct AppState {
-    pub cfg: Config,
+    pub cfg: ServeConfig,
+    pub store: Arc<dyn ActivationCodeStore>,
+    pub broker: Arc<dyn MessageBroker>,
+    /// 发件箱，未配置时为空
+    pub outbox: Option<Arc<Outbox>>,
+    /// 邮件消费者的状态，只运行 WEB 服务时为空
+    pub consumer_state: Option<watch::Receiver<ConsumerState>>,
 }
@@ -1,10 +1,84 @@
 use serde::{Deserialize, Serialize};
 
-use crate::EmailConfig;
+use crate::message::{Message, Registry};
 
+/// 激活码消息
+///
+/// 只包含收件人和激活码，SMTP 配置由消费者从自身的配置中读取。
+/// 旧版本的消息中带有 `email_cfg` 字段，反序列化时会被忽略。
 #[derive(Serialize, Deserialize, Default)]
 pub struct ActiveCode {
     pub email: String,
     pub code: String,
-    pub email_cfg: EmailConfig,
+    /// 激活链接，未配置签名密钥时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// 邮件的语言，为空时使用默认语言
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+}
+
+impl ActiveCode {
+    /// 注册消息类型及旧版本的升级函数
+    ///
+    /// 第 1 版是没有信封的消息，带有 `email_cfg` 字段。
+    pub fn register(registry: &mut Registry) {
+        registry
+            .register::<Self>()
+            .upcaster(Self::TYPE, 1, |mut v| {
+                if let Some(obj) = v.as_object_mut() {
+                    obj.remove("email_cfg");
+                }
+                Ok(v)
+            });
+    }
+}
+
+impl Message for ActiveCode {
+    const TYPE: &'static str = "active_code";
+    const VERSION: u32 = 2;
+}
+
+#[cfg(test)]
+mod test {
+    use super::ActiveCode;
+    use crate::message::{Envelope, Message, RawEnvelope, Registry};
+
+    #[test]
+    fn test_parse_legacy_payload() {
+        let payload = r#"{"email":"team@axum.rs","code":"axum.rs","email_cfg":{"username":"u","password":"p","host":"h"}}"#;
+        let ac: ActiveCode = serde_json::from_str(payload).unwrap();
+        assert_eq!(ac.email, "team@axum.rs");
+        assert_eq!(ac.code, "axum.rs");
+        assert!(ac.link.is_none());
+    }
+
+    #[test]
+    fn test_payload_has_no_credentials() {
+        let ac = ActiveCode {
+            email: "team@axum.rs".to_string(),
+            code: "axum.rs".to_string(),
+            link: None,
+            locale: None,
+        };
+        let payload = serde_json::to_string(&ac).unwrap();
+        assert!(!payload.contains("email_cfg"));
+        assert!(!payload.contains("password"));
+    }
+
+    #[test]
+    fn test_upcast_legacy_payload() {
+        let mut registry = Registry::default();
+        ActiveCode::register(&mut registry);
+
+        let payload = r#"{"email":"team@axum.rs","code":"axum.rs","email_cfg":{"username":"u","password":"p","host":"h"}}"#;
+        let raw = RawEnvelope::from_payload(payload, ActiveCode::TYPE, None).unwrap();
+        assert!(raw.payload.get("email_cfg").is_some());
+        let raw = registry.upcast(raw).unwrap();
+        assert_eq!(raw.version, ActiveCode::VERSION);
+        assert!(raw.payload.get("email_cfg").is_none());
+
+        let env: Envelope<ActiveCode> = registry.decode(raw).unwrap();
+        assert_eq!(env.payload.email, "team@axum.rs");
+    }
 }
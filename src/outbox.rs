@@ -0,0 +1,347 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+use tokio::{
+    sync::{oneshot, Notify},
+    task::JoinHandle,
+};
+
+use crate::{
+    broker::MessageBroker,
+    message::{Envelope, Message, RawEnvelope},
+    model::user::ActiveCode,
+    rabbitmq::handler::Publish,
+    Error, ErrorKind, OutboxConfig, Result,
+};
+
+/// 待发送的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pending {
+    pub id: i64,
+    /// 旧版本写入的消息没有路由键
+    pub routing_key: Option<String>,
+    pub payload: String,
+}
+
+/// 发件箱
+///
+/// 注册时先把消息写入本地的 SQLite 数据库，再由 [`Relay`] 在后台发送到消息队列。
+/// 消息队列不可用时不影响注册，恢复后继续发送。
+pub struct Outbox {
+    conn: Arc<Mutex<Connection>>,
+    notify: Notify,
+}
+
+impl Outbox {
+    /// 打开发件箱，数据库文件不存在时自动创建
+    pub async fn open(path: &str) -> Result<Self> {
+        let path = path.to_string();
+        let conn = blocking(move || {
+            let conn = Connection::open(path)?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS outbox (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    payload TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    sent_at INTEGER,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    last_error TEXT,
+                    dead_at INTEGER,
+                    routing_key TEXT
+                );
+                CREATE INDEX IF NOT EXISTS idx_outbox_pending ON outbox (sent_at, id);",
+            )?;
+            // 旧版本创建的表缺少之后增加的列
+            for (column, ty) in [("dead_at", "INTEGER"), ("routing_key", "TEXT")] {
+                let exists: bool = conn.query_row(
+                    "SELECT COUNT(*) > 0 FROM pragma_table_info('outbox') WHERE name = ?1",
+                    params![column],
+                    |row| row.get(0),
+                )?;
+                if !exists {
+                    conn.execute_batch(&format!(
+                        "ALTER TABLE outbox ADD COLUMN {} {}",
+                        column, ty
+                    ))?;
+                }
+            }
+            Ok(conn)
+        })
+        .await?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            notify: Notify::new(),
+        })
+    }
+
+    /// 写入以 `routing_key` 发送的消息，并通知后台发送
+    pub async fn add(&self, routing_key: &str, payload: &str) -> Result<i64> {
+        let routing_key = routing_key.to_string();
+        let payload = payload.to_string();
+        let id = self
+            .with_conn(move |conn| {
+                conn.execute(
+                    "INSERT INTO outbox (routing_key, payload, created_at) VALUES (?1, ?2, ?3)",
+                    params![routing_key, payload, now()],
+                )?;
+                Ok(conn.last_insert_rowid())
+            })
+            .await?;
+        self.notify.notify_one();
+        Ok(id)
+    }
+
+    /// 按写入顺序获取未发送的消息，不包括已放弃的
+    pub async fn pending(&self, limit: usize) -> Result<Vec<Pending>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, routing_key, payload FROM outbox
+                WHERE sent_at IS NULL AND dead_at IS NULL ORDER BY id LIMIT ?1",
+            )?;
+            let rows = stmt.query_map(params![limit as i64], |row| {
+                Ok(Pending {
+                    id: row.get(0)?,
+                    routing_key: row.get(1)?,
+                    payload: row.get(2)?,
+                })
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// 标记为已发送
+    pub async fn mark_sent(&self, id: i64) -> Result<()> {
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE outbox SET sent_at = ?1 WHERE id = ?2",
+                params![now(), id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// 记录发送失败
+    pub async fn mark_failed(&self, id: i64, reason: &str) -> Result<()> {
+        let reason = reason.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE outbox SET attempts = attempts + 1, last_error = ?1 WHERE id = ?2",
+                params![reason, id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// 放弃发送无法处理的消息，之后不再重试
+    pub async fn mark_dead(&self, id: i64, reason: &str) -> Result<()> {
+        let reason = reason.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE outbox SET attempts = attempts + 1, last_error = ?1, dead_at = ?2
+                WHERE id = ?3",
+                params![reason, now(), id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&conn)
+        })
+        .await
+    }
+}
+
+/// 写入发件箱，由 [`Relay`] 在后台发送
+#[async_trait]
+impl<T: Message + Send + Sync + 'static> Publish<T> for Outbox {
+    async fn publish(&self, routing_key: &str, msg: Envelope<T>) -> Result<()> {
+        self.add(routing_key, &msg.to_payload()?).await?;
+        Ok(())
+    }
+}
+
+/// 在阻塞线程中执行数据库操作
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> rusqlite::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| Error::with_cause(ErrorKind::Outbox, Box::new(e)))?
+        .map_err(Error::from)
+}
+
+/// 后台发送发件箱中的消息
+pub struct Relay {
+    shutdown: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Relay {
+    /// 启动后台发送
+    ///
+    /// 消息以写入时的路由键发送，旧版本写入的消息以 `routing_key` 发送。有新消息写入时立即发送，否则每隔 `relay_interval_secs` 秒检查一次。
+    /// 服务器确认收到后才标记为已发送，因此同一条消息可能被发送多次。
+    pub fn start(
+        outbox: Arc<Outbox>,
+        broker: Arc<dyn MessageBroker>,
+        routing_key: &str,
+        cfg: &OutboxConfig,
+    ) -> Self {
+        let (shutdown, mut shutdown_rx) = oneshot::channel();
+        let interval = Duration::from_secs(cfg.relay_interval_secs);
+        let batch_size = cfg.batch_size;
+        let routing_key = routing_key.to_string();
+
+        let handle = tokio::spawn(async move {
+            loop {
+                if let Err(err) =
+                    relay_once(&outbox, broker.as_ref(), &routing_key, batch_size).await
+                {
+                    tracing::error!("发件箱发送失败：{:?}", err);
+                }
+
+                tokio::select! {
+                    _ = outbox.notify.notified() => {}
+                    _ = tokio::time::sleep(interval) => {}
+                    _ = &mut shutdown_rx => break,
+                }
+            }
+            tracing::info!("发件箱已停止");
+        });
+
+        Self { shutdown, handle }
+    }
+
+    /// 停止后台发送，等待当前批次完成
+    pub async fn shutdown(self) {
+        if self.shutdown.send(()).is_ok() {
+            self.handle.await.ok();
+        }
+    }
+}
+
+/// 发送一批消息，遇到失败时停止，等待下一次重试
+async fn relay_once(
+    outbox: &Outbox,
+    broker: &dyn MessageBroker,
+    routing_key: &str,
+    batch_size: usize,
+) -> Result<()> {
+    for msg in outbox.pending(batch_size).await? {
+        // 旧版本写入的消息没有信封，都是激活码消息
+        let envelope = match RawEnvelope::from_payload(&msg.payload, ActiveCode::TYPE, None) {
+            Ok(envelope) => envelope,
+            Err(err) => {
+                tracing::error!("发件箱中的消息无法解析，已放弃：{}", err.message);
+                outbox.mark_dead(msg.id, &err.message).await?;
+                continue;
+            }
+        };
+        let routing_key = msg.routing_key.as_deref().unwrap_or(routing_key);
+        if let Err(err) = broker.publish(routing_key, &envelope).await {
+            outbox.mark_failed(msg.id, &err.message).await?;
+            return Err(err);
+        }
+        outbox.mark_sent(msg.id).await?;
+    }
+    Ok(())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{Outbox, Pending};
+    use crate::{
+        broker::{memory::MemoryBroker, MessageBroker},
+        message::Envelope,
+        model::user::ActiveCode,
+    };
+
+    #[tokio::test]
+    async fn test_outbox() {
+        let outbox = Outbox::open(":memory:").await.unwrap();
+        let a = outbox.add("user.register", "a").await.unwrap();
+        let b = outbox.add("user.register", "b").await.unwrap();
+
+        let pending = outbox.pending(10).await.unwrap();
+        assert_eq!(
+            pending,
+            vec![
+                Pending {
+                    id: a,
+                    routing_key: Some("user.register".to_string()),
+                    payload: "a".to_string()
+                },
+                Pending {
+                    id: b,
+                    routing_key: Some("user.register".to_string()),
+                    payload: "b".to_string()
+                },
+            ]
+        );
+
+        outbox.mark_failed(a, "broker down").await.unwrap();
+        assert_eq!(outbox.pending(10).await.unwrap().len(), 2);
+
+        outbox.mark_sent(a).await.unwrap();
+        let pending = outbox.pending(10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, b);
+
+        assert_eq!(outbox.pending(0).await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_poison_rows() {
+        let outbox = Outbox::open(":memory:").await.unwrap();
+        let broker = MemoryBroker::default();
+        let consumer = broker.subscribe("test", "#", Arc::new(|_, _| Box::pin(async { Ok(()) })));
+
+        // 无法解析的消息放弃后，不影响之后的消息
+        outbox.add("user.register", "not json").await.unwrap();
+        outbox.add("user.register", "[1, 2").await.unwrap();
+        let good = Envelope::new(ActiveCode::default()).to_payload().unwrap();
+        let good = outbox.add("user.register", &good).await.unwrap();
+
+        super::relay_once(&outbox, &broker, "user.register", 2)
+            .await
+            .unwrap();
+        let pending = outbox.pending(10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, good);
+
+        super::relay_once(&outbox, &broker, "user.register", 2)
+            .await
+            .unwrap();
+        assert!(outbox.pending(10).await.unwrap().is_empty());
+
+        consumer.shutdown(Duration::from_secs(1)).await;
+    }
+}
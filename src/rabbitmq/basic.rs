@@ -1,14 +1,19 @@
 use lapin::{
-    options::{BasicConsumeOptions, BasicPublishOptions, QueueDeclareOptions},
+    options::{BasicConsumeOptions, BasicPublishOptions},
     publisher_confirm::Confirmation,
     types::FieldTable,
-    BasicProperties, Connection, ConnectionProperties, ConsumerDelegate,
+    Connection, ConnectionProperties, ConsumerDelegate,
 };
 
-use crate::{Error, Result};
+use crate::{Error, QueueOptions, Result};
 
 /// 发送消息
-pub async fn send(dsn: &str, queue_name: &str, payload: &str) -> Result<Confirmation> {
+pub async fn send(
+    dsn: &str,
+    queue_name: &str,
+    opts: &QueueOptions,
+    payload: &str,
+) -> Result<Confirmation> {
     // 定义连接属性
     let options = ConnectionProperties::default()
         .with_executor(tokio_executor_trait::Tokio::current())
@@ -25,8 +30,8 @@ pub async fn send(dsn: &str, queue_name: &str, payload: &str) -> Result<Confirma
     let queue = chan
         .queue_declare(
             queue_name,
-            QueueDeclareOptions::default(),
-            FieldTable::default(),
+            super::queue_declare_options(opts),
+            super::queue_arguments(opts, true),
         )
         .await
         .map_err(Error::from)?;
@@ -40,7 +45,7 @@ pub async fn send(dsn: &str, queue_name: &str, payload: &str) -> Result<Confirma
         queue.name().as_str(),
         BasicPublishOptions::default(),
         payload,
-        BasicProperties::default(),
+        super::message_properties(opts),
     )
     .await
     .map_err(Error::from)?
@@ -52,6 +57,7 @@ pub async fn send(dsn: &str, queue_name: &str, payload: &str) -> Result<Confirma
 pub async fn receive<D: ConsumerDelegate + 'static>(
     dsn: &str,
     queue_name: &str,
+    opts: &QueueOptions,
     tag: &str,
     delegate: D,
 ) -> Result<()> {
@@ -71,8 +77,8 @@ pub async fn receive<D: ConsumerDelegate + 'static>(
     let queue = chan
         .queue_declare(
             queue_name,
-            QueueDeclareOptions::default(),
-            FieldTable::default(),
+            super::queue_declare_options(opts),
+            super::queue_arguments(opts, true),
         )
         .await
         .map_err(Error::from)?;
@@ -94,10 +100,18 @@ pub async fn receive<D: ConsumerDelegate + 'static>(
 
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
     use dotenv::dotenv;
-    use lapin::{message::DeliveryResult, options::BasicAckOptions};
 
-    use crate::{Config, Result};
+    use crate::{
+        broker::RawHandler,
+        message::{Envelope, Registry},
+        model::user::ActiveCode,
+        rabbitmq::handler::{self, Handler},
+        Config, QueueOptions, Result,
+    };
 
     const QUEUE_NAME: &str = "AXUM-RS";
 
@@ -109,12 +123,39 @@ mod test {
         Ok(cfg.rabbitmq.dsn.clone())
     }
 
+    /// 打印收到的激活码消息
+    struct Printer;
+
+    #[async_trait]
+    impl Handler<ActiveCode> for Printer {
+        async fn handle(&self, msg: Envelope<ActiveCode>) -> Result<()> {
+            tracing::info!("Received a message: {} {}", msg.id, msg.payload.email);
+            Ok(())
+        }
+    }
+
+    fn printer() -> RawHandler {
+        let mut registry = Registry::default();
+        ActiveCode::register(&mut registry);
+        handler::raw(Arc::new(registry), Arc::new(Printer))
+    }
+
+    fn message(i: usize) -> String {
+        let msg = ActiveCode {
+            email: format!("team{}@axum.rs", i),
+            code: "axum.rs".to_string(),
+            link: None,
+            locale: None,
+        };
+        Envelope::new(msg).to_payload().unwrap()
+    }
+
     #[tokio::test]
     async fn test_basic_send() {
         let dsn = get_dsn().unwrap();
         for i in 0..10 {
-            let msg = format!("#{} AXUM中文网-axum.rs", i);
-            let confirm = super::send(&dsn, QUEUE_NAME, &msg).await;
+            let msg = message(i);
+            let confirm = super::send(&dsn, QUEUE_NAME, &QueueOptions::default(), &msg).await;
             match confirm {
                 Ok(_) => tracing::info!("[x] 消息已发送成功！{}", msg),
                 Err(e) => tracing::error!("{:?}", e),
@@ -129,26 +170,9 @@ mod test {
         super::receive(
             &dsn,
             QUEUE_NAME,
+            &QueueOptions::default(),
             "TESTER",
-            move |delivery: DeliveryResult| async move {
-                tracing::debug!("aaa");
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => {
-                        tracing::error!("None ");
-                        return;
-                    }
-                    Err(err) => {
-                        tracing::error!("Failed to consume queue message {}", err);
-                        return;
-                    }
-                };
-
-                let message = String::from_utf8_lossy(&delivery.data);
-                tracing::info!("Received a message: {}", message);
-
-                delivery.ack(BasicAckOptions::default()).await.unwrap();
-            },
+            handler::delegate(printer()),
         )
         .await
         .unwrap();
@@ -0,0 +1,332 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use lapin::{
+    message::DeliveryResult,
+    options::{BasicCancelOptions, BasicConsumeOptions, BasicQosOptions, ConfirmSelectOptions},
+    types::FieldTable,
+    Channel, Connection, ConsumerDelegate,
+};
+use serde::Serialize;
+use tokio::{
+    sync::{mpsc, oneshot, watch, Semaphore},
+    task::JoinHandle,
+};
+
+use super::topic;
+use crate::{Error, RabbitMQConfig, Result};
+
+/// 消费者状态
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsumerState {
+    /// 正在连接
+    Connecting,
+    /// 正在消费消息
+    Running,
+    /// 连接断开，等待重连
+    Disconnected,
+    /// 已停止
+    Stopped,
+}
+
+/// 受监管的消费者
+pub struct Supervisor {
+    state: watch::Receiver<ConsumerState>,
+    shutdown: oneshot::Sender<Duration>,
+    handle: JoinHandle<()>,
+}
+
+impl Supervisor {
+    pub(crate) fn new(
+        state: watch::Receiver<ConsumerState>,
+        shutdown: oneshot::Sender<Duration>,
+        handle: JoinHandle<()>,
+    ) -> Self {
+        Self {
+            state,
+            shutdown,
+            handle,
+        }
+    }
+
+    /// 消费者的当前状态
+    pub fn state(&self) -> watch::Receiver<ConsumerState> {
+        self.state.clone()
+    }
+
+    /// 停止消费，等待正在处理的消息完成后关闭连接
+    ///
+    /// 超过 `timeout` 仍未完成的消息不会被确认，由服务器在连接关闭后重新投递。
+    pub async fn shutdown(self, timeout: Duration) {
+        if self.shutdown.send(timeout).is_ok() {
+            self.handle.await.ok();
+        }
+    }
+}
+
+/// 启动受监管的消费者
+///
+/// 连接或管道断开后，按指数退避的间隔重新连接、定义交换机和队列并继续消费。
+/// 每次连接成功后，以当前的管道调用 `make_delegate` 创建消息的处理者。
+pub fn supervise<D, F>(cfg: RabbitMQConfig, tag: &str, make_delegate: F) -> Supervisor
+where
+    D: ConsumerDelegate + 'static,
+    F: Fn(Channel) -> D + Send + Sync + 'static,
+{
+    let (state_tx, state_rx) = watch::channel(ConsumerState::Connecting);
+    let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<Duration>();
+    let tag = tag.to_string();
+    // 重连前后共用，保证同时处理的消息数量不超过限制
+    let concurrency = cfg.concurrency.max(1);
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let (draining_tx, draining_rx) = watch::channel(false);
+    let limited = semaphore.clone();
+    let make_delegate = move |chan: Channel| Limited {
+        inner: make_delegate(chan),
+        semaphore: limited.clone(),
+        draining: draining_rx.clone(),
+    };
+
+    let handle = tokio::spawn(async move {
+        let mut attempt = 0;
+        loop {
+            state_tx.send_replace(ConsumerState::Connecting);
+
+            let (err_tx, mut err_rx) = mpsc::unbounded_channel();
+            match consume(&cfg, &tag, &make_delegate, err_tx).await {
+                Ok((conn, chan)) => {
+                    attempt = 0;
+                    state_tx.send_replace(ConsumerState::Running);
+                    tracing::info!("消费者 {} 已启动", tag);
+
+                    tokio::select! {
+                        err = err_rx.recv() => {
+                            tracing::error!("消费者 {} 连接已断开：{:?}", tag, err);
+                        }
+                        timeout = &mut shutdown_rx => {
+                            let timeout = timeout.unwrap_or_default();
+                            // 不再接收新消息
+                            if let Err(err) = chan
+                                .basic_cancel(&tag, BasicCancelOptions::default())
+                                .await
+                            {
+                                tracing::error!("取消消费者 {} 失败：{:?}", tag, err);
+                            }
+                            drain(&semaphore, &draining_tx, concurrency, timeout).await;
+                            if let Err(err) = conn.close(200, "shutdown").await {
+                                tracing::error!("关闭连接失败：{:?}", err);
+                            }
+                            break;
+                        }
+                    }
+                }
+                Err(err) => tracing::error!("消费者 {} 连接失败：{:?}", tag, err),
+            }
+
+            state_tx.send_replace(ConsumerState::Disconnected);
+            let delay = backoff(
+                attempt,
+                Duration::from_secs(cfg.reconnect_min_secs),
+                Duration::from_secs(cfg.reconnect_max_secs),
+            );
+            attempt += 1;
+            tracing::info!("{:?} 后重新连接", delay);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = &mut shutdown_rx => break,
+            }
+        }
+
+        tracing::info!("消费者 {} 已停止", tag);
+        state_tx.send_replace(ConsumerState::Stopped);
+    });
+
+    Supervisor::new(state_rx, shutdown_tx, handle)
+}
+
+/// 等待正在处理的消息完成，之后不再处理任何消息
+///
+/// 尚未开始处理的消息不再等待，由服务器在连接关闭后重新投递。
+async fn drain(
+    semaphore: &Semaphore,
+    draining: &watch::Sender<bool>,
+    concurrency: usize,
+    timeout: Duration,
+) {
+    draining.send_replace(true);
+    match tokio::time::timeout(timeout, semaphore.acquire_many(concurrency as u32)).await {
+        Ok(_) => tracing::info!("正在处理的消息均已完成"),
+        Err(_) => tracing::warn!("等待正在处理的消息超时"),
+    }
+    semaphore.close();
+}
+
+/// 连接并开始消费，连接或管道出错时通过 `err_tx` 通知
+async fn consume<D: ConsumerDelegate + 'static>(
+    cfg: &RabbitMQConfig,
+    tag: &str,
+    make_delegate: &impl Fn(Channel) -> D,
+    err_tx: mpsc::UnboundedSender<lapin::Error>,
+) -> Result<(Connection, Channel)> {
+    let conn = Connection::connect(&cfg.dsn, super::connection_properties())
+        .await
+        .map_err(Error::from)?;
+    let tx = err_tx.clone();
+    conn.on_error(move |err| {
+        tx.send(err).ok();
+    });
+
+    let chan = conn.create_channel().await.map_err(Error::from)?;
+    chan.on_error(move |err| {
+        err_tx.send(err).ok();
+    });
+    // 死信和重试消息通过此管道发布，需要服务器确认
+    chan.confirm_select(ConfirmSelectOptions::default())
+        .await
+        .map_err(Error::from)?;
+    chan.basic_qos(cfg.prefetch_count, BasicQosOptions::default())
+        .await
+        .map_err(Error::from)?;
+
+    let queue = topic::declare(&chan, cfg).await?;
+
+    let consumer = chan
+        .basic_consume(
+            queue.name().as_str(),
+            tag,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .map_err(Error::from)?;
+    consumer.set_delegate(make_delegate(chan.clone()));
+
+    Ok((conn, chan))
+}
+
+/// 限制同时处理的消息数量
+struct Limited<D> {
+    inner: D,
+    semaphore: Arc<Semaphore>,
+    /// 正在停止，尚未开始处理的消息不再处理
+    draining: watch::Receiver<bool>,
+}
+
+impl<D: ConsumerDelegate> ConsumerDelegate for Limited<D> {
+    fn on_new_delivery(
+        &self,
+        delivery: DeliveryResult,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let semaphore = self.semaphore.clone();
+        let mut draining = self.draining.clone();
+        let fut = self.inner.on_new_delivery(delivery);
+        Box::pin(async move {
+            let permit = tokio::select! {
+                biased;
+                _ = draining.wait_for(|d| *d) => return,
+                permit = semaphore.acquire_owned() => permit,
+            };
+            // 信号量关闭说明已经停止，不再处理新的消息
+            if let Ok(_permit) = permit {
+                fut.await
+            }
+        })
+    }
+
+    fn drop_prefetched_messages(&self) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        self.inner.drop_prefetched_messages()
+    }
+}
+
+/// 计算第 `attempt` 次重连前的等待时间
+fn backoff(attempt: u32, min: Duration, max: Duration) -> Duration {
+    min.saturating_mul(2u32.saturating_pow(attempt)).min(max)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use lapin::{message::DeliveryResult, ConsumerDelegate};
+    use tokio::sync::{watch, Semaphore};
+
+    #[tokio::test]
+    async fn test_limited_concurrency() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+
+        let (r, m) = (running.clone(), max.clone());
+        let (_draining_tx, draining) = watch::channel(false);
+        let delegate = super::Limited {
+            inner: move |_: DeliveryResult| {
+                let (r, m) = (r.clone(), m.clone());
+                async move {
+                    let n = r.fetch_add(1, Ordering::SeqCst) + 1;
+                    m.fetch_max(n, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    r.fetch_sub(1, Ordering::SeqCst);
+                }
+            },
+            semaphore: Arc::new(Semaphore::new(2)),
+            draining,
+        };
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| tokio::spawn(delegate.on_new_delivery(Ok(None))))
+            .collect();
+        for h in handles {
+            h.await.unwrap();
+        }
+
+        assert_eq!(max.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_drain_skips_queued() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let semaphore = Arc::new(Semaphore::new(1));
+        let (draining_tx, draining_rx) = watch::channel(false);
+
+        let s = started.clone();
+        let delegate = super::Limited {
+            inner: move |_: DeliveryResult| {
+                let s = s.clone();
+                async move {
+                    s.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+            },
+            semaphore: semaphore.clone(),
+            draining: draining_rx,
+        };
+        let handles: Vec<_> = (0..5)
+            .map(|_| tokio::spawn(delegate.on_new_delivery(Ok(None))))
+            .collect();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // 只等待正在处理的一条消息，排队的消息不再处理
+        let start = std::time::Instant::now();
+        super::drain(&semaphore, &draining_tx, 1, Duration::from_secs(5)).await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        for h in handles {
+            h.await.unwrap();
+        }
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let min = Duration::from_secs(1);
+        let max = Duration::from_secs(60);
+        assert_eq!(super::backoff(0, min, max), Duration::from_secs(1));
+        assert_eq!(super::backoff(3, min, max), Duration::from_secs(8));
+        assert_eq!(super::backoff(10, min, max), max);
+        assert_eq!(super::backoff(100, min, max), max);
+    }
+}
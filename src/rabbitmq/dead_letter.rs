@@ -0,0 +1,108 @@
+use lapin::{
+    message::Delivery,
+    options::{BasicAckOptions, BasicNackOptions, BasicPublishOptions},
+    types::{AMQPValue, FieldTable},
+    Channel,
+};
+
+use crate::RabbitMQConfig;
+
+/// 失败原因
+pub const HEADER_FAILURE_REASON: &str = "x-failure-reason";
+/// 原始交换机
+pub const HEADER_ORIGINAL_EXCHANGE: &str = "x-original-exchange";
+/// 原始路由键
+pub const HEADER_ORIGINAL_ROUTING_KEY: &str = "x-original-routing-key";
+
+/// 将无法处理的消息转到死信队列，并在消息头中记录失败原因
+///
+/// 管道需要开启确认模式，服务器确认收到并成功路由后才确认原消息。
+/// 转发失败时，直接拒绝该消息，由服务器根据队列的 `x-dead-letter-exchange` 参数转到死信队列。
+pub async fn reject(chan: &Channel, cfg: &RabbitMQConfig, delivery: &Delivery, reason: &str) {
+    tracing::warn!("消息已转到死信队列：{}", reason);
+
+    let properties = delivery
+        .properties
+        .clone()
+        .with_headers(failure_headers(delivery, reason));
+    let published = async {
+        let confirmation = chan
+            .basic_publish(
+                &cfg.dead_letter_exchange(),
+                delivery.routing_key.as_str(),
+                BasicPublishOptions {
+                    mandatory: true,
+                    ..Default::default()
+                },
+                &delivery.data,
+                properties,
+            )
+            .await?
+            .await?;
+        super::check_confirmation(confirmation)
+    }
+    .await;
+
+    let result = match published {
+        Ok(()) => delivery.ack(BasicAckOptions::default()).await,
+        Err(err) => {
+            tracing::error!("转到死信队列失败：{:?}", err);
+            delivery
+                .nack(BasicNackOptions {
+                    requeue: false,
+                    ..Default::default()
+                })
+                .await
+        }
+    };
+    if let Err(err) = result {
+        tracing::error!("确认消息失败：{:?}", err);
+    }
+}
+
+/// 在原有消息头的基础上加入失败信息
+fn failure_headers(delivery: &Delivery, reason: &str) -> FieldTable {
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(
+        HEADER_FAILURE_REASON.into(),
+        AMQPValue::LongString(reason.into()),
+    );
+    headers.insert(
+        HEADER_ORIGINAL_EXCHANGE.into(),
+        AMQPValue::LongString(delivery.exchange.as_str().into()),
+    );
+    headers.insert(
+        HEADER_ORIGINAL_ROUTING_KEY.into(),
+        AMQPValue::LongString(delivery.routing_key.as_str().into()),
+    );
+    headers
+}
+
+#[cfg(test)]
+mod test {
+    use lapin::{message::Delivery, types::AMQPValue};
+
+    #[test]
+    fn test_failure_headers() {
+        let delivery = Delivery {
+            delivery_tag: 1,
+            exchange: "axum-rs".into(),
+            routing_key: "active-code".into(),
+            redelivered: false,
+            properties: Default::default(),
+            data: vec![],
+            acker: Default::default(),
+        };
+
+        let headers = super::failure_headers(&delivery, "bad payload");
+        let headers = headers.inner();
+        assert_eq!(
+            headers.get(super::HEADER_FAILURE_REASON),
+            Some(&AMQPValue::LongString("bad payload".into()))
+        );
+        assert_eq!(
+            headers.get(super::HEADER_ORIGINAL_ROUTING_KEY),
+            Some(&AMQPValue::LongString("active-code".into()))
+        );
+    }
+}
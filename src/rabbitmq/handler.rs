@@ -0,0 +1,255 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use lapin::{
+    message::{Delivery, DeliveryResult},
+    options::{BasicAckOptions, BasicNackOptions},
+    Channel, ConsumerDelegate,
+};
+
+use super::{
+    consumer::{self, Supervisor},
+    dead_letter,
+    publisher::Publisher,
+    retry,
+};
+use crate::{
+    broker::RawHandler,
+    message::{Envelope, Message, RawEnvelope, Registry},
+    Error, RabbitMQConfig, Result,
+};
+
+/// 发送 `T` 类型的消息
+#[async_trait]
+pub trait Publish<T: Message>: Send + Sync {
+    /// 以 `routing_key` 发送消息
+    async fn publish(&self, routing_key: &str, msg: Envelope<T>) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: Message + Send + Sync + 'static> Publish<T> for Publisher {
+    async fn publish(&self, routing_key: &str, msg: Envelope<T>) -> Result<()> {
+        self.publish_to(routing_key, &msg).await
+    }
+}
+
+/// 处理 `T` 类型的消息
+///
+/// 解析、确认、重试和转到死信队列由消息代理负责，处理者只需返回处理结果。
+/// 返回可重试的错误（见 [`crate::Error::is_transient`]）时稍后重试，其他错误转到死信队列。
+#[async_trait]
+pub trait Handler<T: Message>: Send + Sync + 'static {
+    async fn handle(&self, msg: Envelope<T>) -> Result<()>;
+}
+
+/// 处理完成后对消息的操作
+#[derive(Debug, PartialEq, Eq)]
+enum Disposition {
+    Ack,
+    Retry(String),
+    Reject(String),
+}
+
+impl Disposition {
+    fn from_result(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Self::Ack,
+            Err(err) if err.is_transient() => Self::Retry(err.message),
+            Err(err) => Self::Reject(err.message),
+        }
+    }
+}
+
+/// 将处理 `T` 类型消息的 `handler` 包装为处理原始载荷的函数
+///
+/// `registry` 中需要注册 `T`，旧版本的消息会先升级到当前版本。没有信封的旧消息视为 `T` 类型。
+pub fn raw<T, H>(registry: Arc<Registry>, handler: Arc<H>) -> RawHandler
+where
+    T: Message + Send + 'static,
+    H: Handler<T>,
+{
+    Arc::new(move |payload: String, message_id: Option<String>| {
+        let registry = registry.clone();
+        let handler = handler.clone();
+        Box::pin(async move {
+            let msg = decode::<T>(&registry, &payload, message_id.as_deref()).map_err(|err| {
+                Error::new(
+                    err.kind,
+                    format!("无法解析消息：{}", err.message),
+                    err.cause,
+                )
+            })?;
+            tracing::info!("收到消息 {}，关联 ID：{:?}", msg.id, msg.correlation_id);
+            handler.handle(msg).await
+        })
+    })
+}
+
+/// 启动受监管的消费者，用 `handler` 处理消息
+pub fn supervise(cfg: RabbitMQConfig, tag: &str, handler: RawHandler) -> Supervisor {
+    let rabbitmq_cfg = cfg.clone();
+    consumer::supervise(cfg, tag, move |chan: Channel| Dispatcher {
+        chan,
+        cfg: rabbitmq_cfg.clone(),
+        handler: handler.clone(),
+    })
+}
+
+/// 将 `handler` 包装为消息的消费者，用于没有重试和死信交换机的简单队列
+///
+/// 处理成功时确认消息，失败时拒绝消息，由服务器根据队列的 `x-dead-letter-exchange` 参数处理。
+pub fn delegate(handler: RawHandler) -> impl ConsumerDelegate {
+    move |delivery: DeliveryResult| {
+        let handler = handler.clone();
+        async move {
+            let (delivery, payload, message_id) = match unpack(delivery) {
+                Some(unpacked) => unpacked,
+                None => return,
+            };
+            let result = match handler(payload, message_id).await {
+                Ok(()) => delivery.ack(BasicAckOptions::default()).await,
+                Err(err) => {
+                    tracing::warn!("消息处理失败，已拒绝：{}", err.message);
+                    delivery
+                        .nack(BasicNackOptions {
+                            requeue: false,
+                            ..Default::default()
+                        })
+                        .await
+                }
+            };
+            if let Err(err) = result {
+                tracing::error!("确认消息失败：{:?}", err);
+            }
+        }
+    }
+}
+
+/// 取出收到的消息及其载荷和 ID
+fn unpack(delivery: DeliveryResult) -> Option<(Delivery, String, Option<String>)> {
+    let delivery = match delivery {
+        Ok(Some(delivery)) => delivery,
+        Ok(None) => return None,
+        Err(err) => {
+            tracing::error!("接收消息失败：{:?}", err);
+            return None;
+        }
+    };
+
+    let payload = String::from_utf8_lossy(&delivery.data).to_string();
+    let message_id = delivery
+        .properties
+        .message_id()
+        .as_ref()
+        .map(|id| id.to_string());
+    Some((delivery, payload, message_id))
+}
+
+/// 将收到的消息交给处理者，并根据处理结果确认、重试或转到死信队列
+#[derive(Clone)]
+struct Dispatcher {
+    chan: Channel,
+    cfg: RabbitMQConfig,
+    handler: RawHandler,
+}
+
+impl Dispatcher {
+    async fn dispatch(self, delivery: DeliveryResult) {
+        let (delivery, payload, message_id) = match unpack(delivery) {
+            Some(unpacked) => unpacked,
+            None => return,
+        };
+        match Disposition::from_result((self.handler)(payload, message_id).await) {
+            Disposition::Ack => {
+                if let Err(err) = delivery.ack(BasicAckOptions::default()).await {
+                    tracing::error!("确认消息失败：{:?}", err);
+                }
+            }
+            Disposition::Retry(reason) => {
+                retry::retry(&self.chan, &self.cfg, &delivery, &reason).await;
+            }
+            Disposition::Reject(reason) => {
+                dead_letter::reject(&self.chan, &self.cfg, &delivery, &reason).await;
+            }
+        }
+    }
+}
+
+impl ConsumerDelegate for Dispatcher {
+    fn on_new_delivery(
+        &self,
+        delivery: DeliveryResult,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(self.clone().dispatch(delivery))
+    }
+}
+
+/// 解析消息，没有信封的旧消息视为 `T` 类型
+fn decode<T: Message>(
+    registry: &Registry,
+    payload: &str,
+    message_id: Option<&str>,
+) -> Result<Envelope<T>> {
+    let raw = RawEnvelope::from_payload(payload, T::TYPE, message_id)?;
+    registry.decode(raw)
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use super::Disposition;
+    use crate::{
+        message::{Envelope, Message, Registry},
+        Error, ErrorKind, SmtpClass,
+    };
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Ping {
+        n: u32,
+    }
+
+    impl Message for Ping {
+        const TYPE: &'static str = "ping";
+        const VERSION: u32 = 1;
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut registry = Registry::default();
+        registry.register::<Ping>();
+
+        let payload = Envelope::new(Ping { n: 1 }).to_payload().unwrap();
+        let msg = super::decode::<Ping>(&registry, &payload, None).unwrap();
+        assert_eq!(msg.payload, Ping { n: 1 });
+
+        let msg = super::decode::<Ping>(&registry, r#"{"n":2}"#, Some("msg-2")).unwrap();
+        assert_eq!(msg.payload, Ping { n: 2 });
+        assert_eq!(msg.id, "msg-2");
+
+        assert!(super::decode::<Ping>(&registry, "not json", None).is_err());
+    }
+
+    #[test]
+    fn test_disposition() {
+        assert_eq!(Disposition::from_result(Ok(())), Disposition::Ack);
+
+        let transient = Error::from_str(
+            ErrorKind::Smtp {
+                code: Some(421),
+                class: SmtpClass::Transient,
+            },
+            "try again",
+        );
+        assert_eq!(
+            Disposition::from_result(Err(transient)),
+            Disposition::Retry("try again".to_string())
+        );
+
+        let permanent = Error::from_str(ErrorKind::Serde, "bad");
+        assert_eq!(
+            Disposition::from_result(Err(permanent)),
+            Disposition::Reject("bad".to_string())
+        );
+    }
+}
@@ -1,2 +1,212 @@
+use lapin::{
+    options::{ExchangeDeclareOptions, QueueDeclareOptions},
+    publisher_confirm::Confirmation,
+    types::{AMQPValue, FieldTable},
+    BasicProperties, ConnectionProperties,
+};
+
+use crate::{DeliveryMode, Error, ErrorKind, QueueOptions, Result};
+
 pub mod basic;
+pub mod consumer;
+pub mod dead_letter;
+pub mod handler;
+pub mod publisher;
+pub mod retry;
 pub mod topic;
+
+/// 使用 tokio 作为执行器的连接属性
+pub fn connection_properties() -> ConnectionProperties {
+    ConnectionProperties::default()
+        .with_executor(tokio_executor_trait::Tokio::current())
+        .with_reactor(tokio_reactor_trait::Tokio)
+}
+
+/// 定义队列时的选项
+pub fn queue_declare_options(opts: &QueueOptions) -> QueueDeclareOptions {
+    QueueDeclareOptions {
+        durable: opts.durable,
+        auto_delete: opts.auto_delete,
+        exclusive: opts.exclusive,
+        ..Default::default()
+    }
+}
+
+/// 定义交换机时的选项
+pub fn exchange_declare_options(opts: &QueueOptions) -> ExchangeDeclareOptions {
+    ExchangeDeclareOptions {
+        durable: opts.durable,
+        ..Default::default()
+    }
+}
+
+/// 定义队列时的参数
+///
+/// `with_limits` 为 `false` 时只包含队列类型，不包含有效期、最大长度等限制，用于死信队列和延迟队列。
+pub fn queue_arguments(opts: &QueueOptions, with_limits: bool) -> FieldTable {
+    let mut args = FieldTable::default();
+    if let Some(queue_type) = &opts.queue_type {
+        args.insert(
+            "x-queue-type".into(),
+            AMQPValue::LongString(queue_type.as_str().into()),
+        );
+    }
+    if !with_limits {
+        return args;
+    }
+    if let Some(ttl) = opts.message_ttl {
+        args.insert("x-message-ttl".into(), AMQPValue::LongLongInt(ttl as i64));
+    }
+    if let Some(max_length) = opts.max_length {
+        args.insert(
+            "x-max-length".into(),
+            AMQPValue::LongLongInt(max_length as i64),
+        );
+    }
+    if let Some(overflow) = &opts.overflow {
+        args.insert(
+            "x-overflow".into(),
+            AMQPValue::LongString(overflow.as_str().into()),
+        );
+    }
+    args
+}
+
+/// 死信队列和延迟队列的选项
+///
+/// 这些队列不能随消费者的连接一起删除，始终为非独占、不自动删除的队列，只沿用配置中的持久化和队列类型。
+pub fn infrastructure_options(opts: &QueueOptions) -> QueueOptions {
+    QueueOptions {
+        durable: opts.durable,
+        queue_type: opts.queue_type.clone(),
+        ..Default::default()
+    }
+}
+
+/// 发布消息时的属性
+pub fn message_properties(opts: &QueueOptions) -> BasicProperties {
+    let mode = match opts.delivery_mode {
+        DeliveryMode::Transient => 1,
+        DeliveryMode::Persistent => 2,
+    };
+    BasicProperties::default().with_delivery_mode(mode)
+}
+
+/// 检查服务器对发布的消息的确认结果
+///
+/// 需要在管道上开启确认模式，并以 `mandatory` 发布消息，无法路由的消息会被退回。
+pub fn check_confirmation(confirmation: Confirmation) -> Result<()> {
+    match confirmation {
+        Confirmation::Ack(None) => Ok(()),
+        Confirmation::Ack(Some(msg)) | Confirmation::Nack(Some(msg)) => Err(Error::new(
+            ErrorKind::RabbitMQ,
+            format!(
+                "消息无法路由：{} {}",
+                msg.reply_code,
+                msg.reply_text.as_str()
+            ),
+            None,
+        )),
+        Confirmation::Nack(None) => Err(Error::from_str(ErrorKind::RabbitMQ, "服务器拒绝了消息")),
+        Confirmation::NotRequested => {
+            Err(Error::from_str(ErrorKind::RabbitMQ, "管道未开启确认模式"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lapin::{
+        message::{BasicReturnMessage, Delivery},
+        publisher_confirm::Confirmation,
+        types::AMQPValue,
+    };
+
+    use crate::{DeliveryMode, QueueOptions};
+
+    #[test]
+    fn test_queue_options() {
+        let opts = QueueOptions {
+            message_ttl: Some(60000),
+            max_length: Some(10),
+            overflow: Some("reject-publish".to_string()),
+            queue_type: Some("quorum".to_string()),
+            ..Default::default()
+        };
+        assert!(super::queue_declare_options(&opts).durable);
+        assert!(super::exchange_declare_options(&opts).durable);
+
+        let args = super::queue_arguments(&opts, true);
+        let args = args.inner();
+        assert_eq!(
+            args.get("x-queue-type"),
+            Some(&AMQPValue::LongString("quorum".into()))
+        );
+        assert_eq!(
+            args.get("x-message-ttl"),
+            Some(&AMQPValue::LongLongInt(60000))
+        );
+        assert_eq!(args.get("x-max-length"), Some(&AMQPValue::LongLongInt(10)));
+        assert_eq!(
+            args.get("x-overflow"),
+            Some(&AMQPValue::LongString("reject-publish".into()))
+        );
+
+        let args = super::queue_arguments(&opts, false);
+        assert_eq!(args.inner().len(), 1);
+
+        let opts = QueueOptions {
+            durable: false,
+            auto_delete: true,
+            exclusive: true,
+            ..opts
+        };
+        let infra = super::infrastructure_options(&opts);
+        let declare = super::queue_declare_options(&infra);
+        assert!(!declare.durable);
+        assert!(!declare.auto_delete);
+        assert!(!declare.exclusive);
+        let args = super::queue_arguments(&infra, true);
+        assert_eq!(args.inner().len(), 1);
+        assert_eq!(
+            args.inner().get("x-queue-type"),
+            Some(&AMQPValue::LongString("quorum".into()))
+        );
+    }
+
+    #[test]
+    fn test_message_properties() {
+        let opts = QueueOptions::default();
+        assert_eq!(*super::message_properties(&opts).delivery_mode(), Some(2));
+
+        let opts = QueueOptions {
+            delivery_mode: DeliveryMode::Transient,
+            ..Default::default()
+        };
+        assert_eq!(*super::message_properties(&opts).delivery_mode(), Some(1));
+    }
+
+    #[test]
+    fn test_check_confirmation() {
+        assert!(super::check_confirmation(Confirmation::Ack(None)).is_ok());
+        assert!(super::check_confirmation(Confirmation::Nack(None)).is_err());
+        assert!(super::check_confirmation(Confirmation::NotRequested).is_err());
+
+        let returned = BasicReturnMessage {
+            delivery: Delivery {
+                delivery_tag: 0,
+                exchange: "axum-rs".into(),
+                routing_key: "active-code".into(),
+                redelivered: false,
+                properties: Default::default(),
+                data: vec![],
+                acker: Default::default(),
+            },
+            reply_code: 312,
+            reply_text: "NO_ROUTE".into(),
+        };
+        let err =
+            super::check_confirmation(Confirmation::Ack(Some(Box::new(returned)))).unwrap_err();
+        assert!(err.message.contains("NO_ROUTE"));
+    }
+}
@@ -0,0 +1,148 @@
+use lapin::{
+    options::{BasicPublishOptions, ConfirmSelectOptions},
+    publisher_confirm::Confirmation,
+    BasicProperties, Channel, Connection,
+};
+use tokio::sync::Mutex;
+
+use serde::Serialize;
+
+use super::topic;
+use crate::{message::Envelope, Error, RabbitMQConfig, Result};
+
+/// 长连接的发布者
+///
+/// 启动时定义好交换机和队列，之后复用同一个连接和管道池发送消息。
+/// 连接断开后，在下一次发送时自动重连并重新定义交换机和队列。
+pub struct Publisher {
+    cfg: RabbitMQConfig,
+    conn: Mutex<Option<Connection>>,
+    channels: Mutex<Vec<Channel>>,
+}
+
+impl Publisher {
+    /// 连接到服务器并定义交换机和队列
+    pub async fn connect(cfg: &RabbitMQConfig) -> Result<Self> {
+        let publisher = Self::lazy(cfg);
+
+        let chan = publisher.acquire().await?;
+        publisher.release(chan).await;
+
+        Ok(publisher)
+    }
+
+    /// 创建发布者，第一次发送消息时才连接
+    pub fn lazy(cfg: &RabbitMQConfig) -> Self {
+        Self {
+            cfg: cfg.clone(),
+            conn: Mutex::new(None),
+            channels: Mutex::new(Vec::with_capacity(cfg.channel_pool_size)),
+        }
+    }
+
+    /// 以配置的路由键发送消息，服务器确认收到并成功路由到队列后才返回
+    pub async fn publish<T: Serialize>(&self, envelope: &Envelope<T>) -> Result<()> {
+        self.publish_to(&self.cfg.routing_key, envelope).await
+    }
+
+    /// 以 `routing_key` 发送消息，服务器确认收到并成功路由到队列后才返回
+    pub async fn publish_to<T: Serialize>(
+        &self,
+        routing_key: &str,
+        envelope: &Envelope<T>,
+    ) -> Result<()> {
+        let payload = envelope.to_payload()?;
+        let properties = envelope.properties(super::message_properties(&self.cfg.queue));
+        let mut chan = self.acquire().await?;
+        let confirmation = match self
+            .publish_on(&chan, routing_key, &payload, &properties)
+            .await
+        {
+            Ok(confirmation) => confirmation,
+            Err(err) => {
+                // 连接可能已经断开，重新获取管道后再试一次
+                tracing::warn!("发送消息失败，正在重试：{:?}", err);
+                chan = self.acquire().await?;
+                self.publish_on(&chan, routing_key, &payload, &properties)
+                    .await
+                    .map_err(Error::from)?
+            }
+        };
+        self.release(chan).await;
+        super::check_confirmation(confirmation)
+    }
+
+    /// 关闭连接
+    pub async fn close(&self) {
+        self.channels.lock().await.clear();
+        if let Some(conn) = self.conn.lock().await.take() {
+            if let Err(err) = conn.close(200, "shutdown").await {
+                tracing::error!("关闭连接失败：{:?}", err);
+            }
+        }
+    }
+
+    async fn publish_on(
+        &self,
+        chan: &Channel,
+        routing_key: &str,
+        payload: &str,
+        properties: &BasicProperties,
+    ) -> std::result::Result<Confirmation, lapin::Error> {
+        chan.basic_publish(
+            &self.cfg.exchange_name,
+            routing_key,
+            BasicPublishOptions {
+                mandatory: true,
+                ..Default::default()
+            },
+            payload.as_bytes(),
+            properties.clone(),
+        )
+        .await?
+        .await
+    }
+
+    /// 从池中取出管道，池为空时新建管道
+    async fn acquire(&self) -> Result<Channel> {
+        while let Some(chan) = self.channels.lock().await.pop() {
+            if chan.status().connected() {
+                return Ok(chan);
+            }
+        }
+
+        let mut conn = self.conn.lock().await;
+        if let Some(c) = conn.as_ref() {
+            if c.status().connected() {
+                let chan = c.create_channel().await.map_err(Error::from)?;
+                chan.confirm_select(ConfirmSelectOptions::default())
+                    .await
+                    .map_err(Error::from)?;
+                return Ok(chan);
+            }
+        }
+
+        tracing::info!("正在连接 RabbitMQ");
+        let c = Connection::connect(&self.cfg.dsn, super::connection_properties())
+            .await
+            .map_err(Error::from)?;
+        let chan = c.create_channel().await.map_err(Error::from)?;
+        chan.confirm_select(ConfirmSelectOptions::default())
+            .await
+            .map_err(Error::from)?;
+        topic::declare(&chan, &self.cfg).await?;
+
+        self.channels.lock().await.clear();
+        *conn = Some(c);
+
+        Ok(chan)
+    }
+
+    /// 将管道放回池中
+    async fn release(&self, chan: Channel) {
+        let mut channels = self.channels.lock().await;
+        if chan.status().connected() && channels.len() < self.cfg.channel_pool_size {
+            channels.push(chan);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use lapin::{
+    message::Delivery,
+    options::{BasicAckOptions, BasicNackOptions, BasicPublishOptions},
+    types::AMQPValue,
+    Channel,
+};
+
+use super::dead_letter;
+use crate::{RabbitMQConfig, Result};
+
+/// 重试次数
+pub const HEADER_RETRY_COUNT: &str = "x-retry-count";
+
+/// 延迟队列的名称
+pub fn queue_name(cfg: &RabbitMQConfig, delay_secs: u64) -> String {
+    format!("{}.retry.{}s", cfg.queue_name, delay_secs)
+}
+
+/// 定义延迟队列
+///
+/// 延迟队列没有消费者，消息过期后由服务器通过死信机制重新投递到原交换机。
+pub async fn declare(chan: &Channel, cfg: &RabbitMQConfig) -> Result<()> {
+    let opts = super::infrastructure_options(&cfg.queue);
+    for delay in cfg.retry_delays()? {
+        let mut args = super::queue_arguments(&opts, false);
+        args.insert(
+            "x-message-ttl".into(),
+            AMQPValue::LongLongInt((delay * 1000) as i64),
+        );
+        args.insert(
+            "x-dead-letter-exchange".into(),
+            AMQPValue::LongString(cfg.exchange_name.as_str().into()),
+        );
+        args.insert(
+            "x-dead-letter-routing-key".into(),
+            AMQPValue::LongString(cfg.routing_key.as_str().into()),
+        );
+        chan.queue_declare(
+            &queue_name(cfg, delay),
+            super::queue_declare_options(&opts),
+            args,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// 获取消息已重试的次数
+pub fn retry_count(delivery: &Delivery) -> u32 {
+    let value = delivery
+        .properties
+        .headers()
+        .as_ref()
+        .and_then(|h| h.inner().get(HEADER_RETRY_COUNT).cloned());
+    match value {
+        Some(AMQPValue::ShortShortUInt(n)) => n as u32,
+        Some(AMQPValue::ShortUInt(n)) => n as u32,
+        Some(AMQPValue::LongUInt(n)) => n,
+        Some(AMQPValue::ShortShortInt(n)) => n.max(0) as u32,
+        Some(AMQPValue::ShortInt(n)) => n.max(0) as u32,
+        Some(AMQPValue::LongInt(n)) => n.max(0) as u32,
+        Some(AMQPValue::LongLongInt(n)) => n.clamp(0, u32::MAX as i64) as u32,
+        _ => 0,
+    }
+}
+
+/// 第 `attempt` 次重试的等待时间
+fn delay_for(delays: &[u64], attempt: u32) -> Option<u64> {
+    let idx = (attempt.max(1) as usize - 1).min(delays.len().checked_sub(1)?);
+    delays.get(idx).copied()
+}
+
+/// 将处理失败的消息放入延迟队列，等待重试
+///
+/// 超过最大重试次数或没有配置延迟队列时，转到死信队列。
+pub async fn retry(chan: &Channel, cfg: &RabbitMQConfig, delivery: &Delivery, reason: &str) {
+    let attempt = retry_count(delivery) + 1;
+    let delays = cfg.retry_delays().unwrap_or_default();
+    let delay = match delay_for(&delays, attempt) {
+        Some(delay) if attempt <= cfg.max_retries => delay,
+        _ => {
+            let reason = format!("{}（已重试 {} 次）", reason, attempt - 1);
+            dead_letter::reject(chan, cfg, delivery, &reason).await;
+            return;
+        }
+    };
+    tracing::warn!("{}，{} 秒后第 {} 次重试", reason, delay, attempt);
+
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(HEADER_RETRY_COUNT.into(), AMQPValue::LongUInt(attempt));
+    headers.insert(
+        dead_letter::HEADER_FAILURE_REASON.into(),
+        AMQPValue::LongString(reason.into()),
+    );
+    let properties = delivery.properties.clone().with_headers(headers);
+
+    // 通过默认交换机直接投递到延迟队列，服务器确认收到并成功路由后才确认原消息
+    let published = async {
+        let confirmation = chan
+            .basic_publish(
+                "",
+                &queue_name(cfg, delay),
+                BasicPublishOptions {
+                    mandatory: true,
+                    ..Default::default()
+                },
+                &delivery.data,
+                properties,
+            )
+            .await?
+            .await?;
+        super::check_confirmation(confirmation)
+    }
+    .await;
+
+    let result = match published {
+        Ok(()) => delivery.ack(BasicAckOptions::default()).await,
+        Err(err) => {
+            // 放入延迟队列失败，退回原队列
+            tracing::error!("放入延迟队列失败：{:?}", err);
+            delivery
+                .nack(BasicNackOptions {
+                    requeue: true,
+                    ..Default::default()
+                })
+                .await
+        }
+    };
+    if let Err(err) = result {
+        tracing::error!("确认消息失败：{:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use lapin::{
+        message::Delivery,
+        types::{AMQPValue, FieldTable},
+        BasicProperties,
+    };
+
+    fn delivery(headers: Option<FieldTable>) -> Delivery {
+        let mut properties = BasicProperties::default();
+        if let Some(headers) = headers {
+            properties = properties.with_headers(headers);
+        }
+        Delivery {
+            delivery_tag: 1,
+            exchange: "axum-rs".into(),
+            routing_key: "active-code".into(),
+            redelivered: false,
+            properties,
+            data: vec![],
+            acker: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_retry_count() {
+        assert_eq!(super::retry_count(&delivery(None)), 0);
+
+        let mut headers = FieldTable::default();
+        headers.insert(super::HEADER_RETRY_COUNT.into(), AMQPValue::LongUInt(2));
+        assert_eq!(super::retry_count(&delivery(Some(headers))), 2);
+
+        let mut headers = FieldTable::default();
+        headers.insert(super::HEADER_RETRY_COUNT.into(), AMQPValue::LongLongInt(3));
+        assert_eq!(super::retry_count(&delivery(Some(headers))), 3);
+    }
+
+    #[test]
+    fn test_delay_for() {
+        let delays = [10, 60, 600];
+        assert_eq!(super::delay_for(&delays, 1), Some(10));
+        assert_eq!(super::delay_for(&delays, 3), Some(600));
+        assert_eq!(super::delay_for(&delays, 5), Some(600));
+        assert_eq!(super::delay_for(&[], 1), None);
+    }
+}
@@ -1,40 +1,65 @@
 use lapin::{
-    options::{
-        BasicConsumeOptions, BasicPublishOptions, ExchangeDeclareOptions, QueueBindOptions,
-        QueueDeclareOptions,
-    },
-    types::FieldTable,
-    BasicProperties, Connection, ConnectionProperties, ConsumerDelegate,
+    options::{BasicConsumeOptions, BasicPublishOptions, ConfirmSelectOptions, QueueBindOptions},
+    types::{AMQPValue, FieldTable},
+    Channel, Connection, ConsumerDelegate, Queue,
 };
 
-pub async fn send(
-    dsn: &str,
+use crate::{QueueOptions, RabbitMQConfig};
+
+/// 根据配置定义交换机和队列，包括死信交换机、死信队列和延迟重试队列
+pub async fn declare(chan: &Channel, cfg: &RabbitMQConfig) -> crate::Result<Queue> {
+    let dlx = cfg.dead_letter_exchange();
+    let infra = super::infrastructure_options(&cfg.queue);
+    bind_queue(
+        chan,
+        &dlx,
+        &cfg.dead_letter_queue(),
+        &cfg.routing_key,
+        &infra,
+        super::queue_arguments(&infra, false),
+    )
+    .await?;
+
+    // 被拒绝的消息转到死信交换机
+    let mut args = super::queue_arguments(&cfg.queue, true);
+    args.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString(dlx.into()),
+    );
+    let queue = bind_queue(
+        chan,
+        &cfg.exchange_name,
+        &cfg.queue_name,
+        &cfg.routing_key,
+        &cfg.queue,
+        args,
+    )
+    .await?;
+
+    super::retry::declare(chan, cfg).await?;
+
+    Ok(queue)
+}
+
+/// 定义交换机和队列，并将队列绑定到交换机
+async fn bind_queue(
+    chan: &Channel,
     exchange: &str,
     queue_name: &str,
     routing_key: &str,
-    payload: &str,
-) -> Result<(), lapin::Error> {
-    let options = ConnectionProperties::default()
-        .with_executor(tokio_executor_trait::Tokio::current())
-        .with_reactor(tokio_reactor_trait::Tokio);
-
-    let conn = Connection::connect(dsn, options).await?;
-    let chan = conn.create_channel().await?;
-
+    opts: &QueueOptions,
+    args: FieldTable,
+) -> Result<Queue, lapin::Error> {
     chan.exchange_declare(
         exchange,
         lapin::ExchangeKind::Topic,
-        ExchangeDeclareOptions::default(),
+        super::exchange_declare_options(opts),
         FieldTable::default(),
     )
     .await?;
 
     let queue = chan
-        .queue_declare(
-            queue_name,
-            QueueDeclareOptions::default(),
-            FieldTable::default(),
-        )
+        .queue_declare(queue_name, super::queue_declare_options(opts), args)
         .await?;
 
     chan.queue_bind(
@@ -46,59 +71,47 @@ pub async fn send(
     )
     .await?;
 
+    Ok(queue)
+}
+
+/// 发送消息，服务器确认收到并成功路由到队列后才返回
+///
+/// 交换机和队列按 [`declare`] 定义，与消费者使用的参数相同。
+pub async fn send(cfg: &RabbitMQConfig, payload: &str) -> crate::Result<()> {
+    let conn = Connection::connect(&cfg.dsn, super::connection_properties()).await?;
+    let chan = conn.create_channel().await?;
+    chan.confirm_select(ConfirmSelectOptions::default()).await?;
+
+    declare(&chan, cfg).await?;
+
     let payload = payload.as_bytes();
 
-    chan.basic_publish(
-        exchange,
-        routing_key,
-        BasicPublishOptions::default(),
-        payload,
-        BasicProperties::default(),
-    )
-    .await?
-    .await?;
-    Ok(())
+    let confirmation = chan
+        .basic_publish(
+            &cfg.exchange_name,
+            &cfg.routing_key,
+            BasicPublishOptions {
+                mandatory: true,
+                ..Default::default()
+            },
+            payload,
+            super::message_properties(&cfg.queue),
+        )
+        .await?
+        .await?;
+    super::check_confirmation(confirmation)
 }
 
+/// 接收消息，交换机和队列按 [`declare`] 定义
 pub async fn receive<D: ConsumerDelegate + 'static>(
-    dsn: &str,
-    exchange: &str,
-    queue_name: &str,
-    routing_key: &str,
+    cfg: &RabbitMQConfig,
     tag: &str,
     delegate: D,
-) -> Result<(), lapin::Error> {
-    let options = ConnectionProperties::default()
-        .with_executor(tokio_executor_trait::Tokio::current())
-        .with_reactor(tokio_reactor_trait::Tokio);
-
-    let conn = Connection::connect(dsn, options).await?;
+) -> crate::Result<()> {
+    let conn = Connection::connect(&cfg.dsn, super::connection_properties()).await?;
     let chan = conn.create_channel().await?;
 
-    chan.exchange_declare(
-        exchange,
-        lapin::ExchangeKind::Topic,
-        ExchangeDeclareOptions::default(),
-        FieldTable::default(),
-    )
-    .await?;
-
-    let queue = chan
-        .queue_declare(
-            queue_name,
-            QueueDeclareOptions::default(),
-            FieldTable::default(),
-        )
-        .await?;
-
-    chan.queue_bind(
-        queue.name().as_str(),
-        exchange,
-        routing_key,
-        QueueBindOptions::default(),
-        FieldTable::default(),
-    )
-    .await?;
+    let queue = declare(&chan, cfg).await?;
 
     let consumer = chan
         .basic_consume(
@@ -111,34 +124,74 @@ pub async fn receive<D: ConsumerDelegate + 'static>(
 
     consumer.set_delegate(delegate);
 
-    conn.run()
+    Ok(conn.run()?)
 }
 
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
     use dotenv::dotenv;
-    use lapin::{message::DeliveryResult, options::BasicAckOptions};
 
-    use crate::{Config, Result};
+    use crate::{
+        broker::RawHandler,
+        message::{Envelope, Registry},
+        model::user::ActiveCode,
+        rabbitmq::handler::{self, Handler},
+        Config, RabbitMQConfig, Result,
+    };
 
     const QUEUE_NAME: &str = "AXUM-RS";
     const EXCHANGE_NAME: &str = "USER-REGISTER";
     const ROUTING_KEY: &str = "AXUM-RS";
 
-    fn get_dsn() -> Result<String> {
+    fn get_cfg() -> Result<RabbitMQConfig> {
         dotenv().ok();
         tracing_subscriber::fmt().init();
 
         let cfg = Config::from_env()?;
-        Ok(cfg.rabbitmq.dsn.clone())
+        Ok(RabbitMQConfig {
+            exchange_name: EXCHANGE_NAME.to_string(),
+            queue_name: QUEUE_NAME.to_string(),
+            routing_key: ROUTING_KEY.to_string(),
+            ..cfg.rabbitmq
+        })
+    }
+
+    /// 打印收到的激活码消息
+    struct Printer;
+
+    #[async_trait]
+    impl Handler<ActiveCode> for Printer {
+        async fn handle(&self, msg: Envelope<ActiveCode>) -> Result<()> {
+            tracing::info!("Received a message: {} {}", msg.id, msg.payload.email);
+            Ok(())
+        }
+    }
+
+    fn printer() -> RawHandler {
+        let mut registry = Registry::default();
+        ActiveCode::register(&mut registry);
+        handler::raw(Arc::new(registry), Arc::new(Printer))
+    }
+
+    fn message(i: usize) -> String {
+        let msg = ActiveCode {
+            email: format!("team{}@axum.rs", i),
+            code: "axum.rs".to_string(),
+            link: None,
+            locale: None,
+        };
+        Envelope::new(msg).to_payload().unwrap()
     }
 
     #[tokio::test]
     async fn test_topic_send() {
-        let dsn = get_dsn().unwrap();
+        let cfg = get_cfg().unwrap();
         for i in 0..10 {
-            let msg = format!("#{} AXUM中文网-axum.rs", i);
-            let confirm = super::send(&dsn, EXCHANGE_NAME, QUEUE_NAME, ROUTING_KEY, &msg).await;
+            let msg = message(i);
+            let confirm = super::send(&cfg, &msg).await;
             match confirm {
                 Ok(_) => tracing::info!("[x] 消息已发送成功！{}", msg),
                 Err(e) => tracing::error!("{:?}", e),
@@ -149,34 +202,9 @@ mod test {
     }
     #[tokio::test]
     async fn test_topic_receive() {
-        let dsn = get_dsn().unwrap();
-        super::receive(
-            &dsn,
-            EXCHANGE_NAME,
-            QUEUE_NAME,
-            ROUTING_KEY,
-            "TESTER",
-            move |delivery: DeliveryResult| async move {
-                tracing::debug!("aaa");
-                let delivery = match delivery {
-                    Ok(Some(delivery)) => delivery,
-                    Ok(None) => {
-                        tracing::error!("None ");
-                        return;
-                    }
-                    Err(err) => {
-                        tracing::error!("Failed to consume queue message {}", err);
-                        return;
-                    }
-                };
-
-                let message = String::from_utf8_lossy(&delivery.data);
-                tracing::info!("Received a message: {}", message);
-
-                delivery.ack(BasicAckOptions::default()).await.unwrap();
-            },
-        )
-        .await
-        .unwrap();
+        let cfg = get_cfg().unwrap();
+        super::receive(&cfg, "TESTER", handler::delegate(printer()))
+            .await
+            .unwrap();
     }
 }
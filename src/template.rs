@@ -0,0 +1,198 @@
+use serde::Serialize;
+use tera::{Context, Tera};
+
+use crate::{i18n, model::user::ActiveCode, Error, ErrorKind, Result, TemplateConfig};
+
+/// 内置的模板
+///
+/// 以 `{类型}/{部分}` 命名，部分为 `subject.txt`、`text.txt` 和 `html.html`。
+/// 标题必须提供，纯文本和 HTML 至少提供一个。
+/// 模板中的文本通过 `t` 从语言包中读取，也可以用 `{类型}/{语言}/{部分}` 为某个语言单独提供模板。
+const EMBEDDED: &[(&str, &str)] = &[
+    (
+        "active_code/subject.txt",
+        include_str!("../templates/active_code/subject.txt"),
+    ),
+    (
+        "active_code/text.txt",
+        include_str!("../templates/active_code/text.txt"),
+    ),
+    (
+        "active_code/html.html",
+        include_str!("../templates/active_code/html.html"),
+    ),
+];
+
+/// 可以渲染为邮件的消息
+pub trait EmailTemplate: Serialize {
+    /// 模板的类型
+    const NAME: &'static str;
+}
+
+impl EmailTemplate for ActiveCode {
+    const NAME: &'static str = "active_code";
+}
+
+/// 渲染后的邮件内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    pub subject: String,
+    pub text: Option<String>,
+    pub html: Option<String>,
+}
+
+pub struct Templates {
+    tera: Tera,
+}
+
+impl Templates {
+    /// 加载模板目录中的模板，目录中没有的使用内置模板
+    pub fn load(cfg: &TemplateConfig) -> Result<Self> {
+        let mut tera = if cfg.dir.is_empty() {
+            Tera::default()
+        } else {
+            Tera::new(&format!("{}/**/*", cfg.dir.trim_end_matches('/')))?
+        };
+        tera.extend(&embedded()?)?;
+        Ok(Self { tera })
+    }
+
+    /// 以 `locale` 语言渲染邮件
+    ///
+    /// 不支持的语言使用默认语言。该语言单独提供了标题模板时使用它的整套模板，否则使用通用模板。
+    pub fn render<T: EmailTemplate>(&self, msg: &T, locale: &str) -> Result<Rendered> {
+        let locale = i18n::resolve(locale).unwrap_or(i18n::DEFAULT_LOCALE);
+        let mut ctx = Context::from_serialize(msg)?;
+        ctx.insert("locale", locale);
+        ctx.insert("t", i18n::catalog(locale));
+        let localized = format!("{}/{}", T::NAME, locale);
+        let prefix = if self.has(&localized, "subject.txt") {
+            localized.as_str()
+        } else {
+            T::NAME
+        };
+
+        let subject = self
+            .render_part(prefix, "subject.txt", &ctx)?
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::Template,
+                    format!("找不到模板：{}/subject.txt", prefix),
+                    None,
+                )
+            })?;
+        let text = self.render_part(prefix, "text.txt", &ctx)?;
+        let html = self.render_part(prefix, "html.html", &ctx)?;
+        if text.is_none() && html.is_none() {
+            return Err(Error::new(
+                ErrorKind::Template,
+                format!("模板 {} 缺少邮件内容", prefix),
+                None,
+            ));
+        }
+
+        Ok(Rendered {
+            subject: subject.trim().to_string(),
+            text: text.map(|s| s.trim().to_string()),
+            html,
+        })
+    }
+
+    fn has(&self, prefix: &str, part: &str) -> bool {
+        let template = format!("{}/{}", prefix, part);
+        self.tera.get_template_names().any(|n| n == template)
+    }
+
+    /// 渲染邮件的一部分，没有该部分的模板时返回 `None`
+    fn render_part(&self, prefix: &str, part: &str, ctx: &Context) -> Result<Option<String>> {
+        if !self.has(prefix, part) {
+            return Ok(None);
+        }
+        self.tera
+            .render(&format!("{}/{}", prefix, part), ctx)
+            .map(Some)
+            .map_err(Error::from)
+    }
+}
+
+fn embedded() -> Result<Tera> {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(EMBEDDED.iter().copied())?;
+    Ok(tera)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Templates;
+    use crate::{i18n::DEFAULT_LOCALE, model::user::ActiveCode, TemplateConfig};
+
+    fn active_code(link: Option<&str>) -> ActiveCode {
+        ActiveCode {
+            email: "team@axum.rs".to_string(),
+            code: "123456".to_string(),
+            link: link.map(str::to_string),
+            locale: None,
+        }
+    }
+
+    #[test]
+    fn test_embedded_templates() {
+        let templates = Templates::load(&TemplateConfig::default()).unwrap();
+        let rendered = templates
+            .render(
+                &active_code(Some("https://axum.rs/active/t")),
+                DEFAULT_LOCALE,
+            )
+            .unwrap();
+        assert_eq!(rendered.subject, "激活账号");
+        assert_eq!(
+            rendered.text.unwrap(),
+            "你的激活码是：123456\n或者点击以下链接激活：https://axum.rs/active/t"
+        );
+        let html = rendered.html.unwrap();
+        assert!(html.contains("123456"));
+        assert!(html.contains("立即激活"));
+
+        let rendered = templates.render(&active_code(None), "xx").unwrap();
+        assert_eq!(rendered.text.unwrap(), "你的激活码是：123456");
+        assert!(!rendered.html.unwrap().contains("立即激活"));
+    }
+
+    #[test]
+    fn test_localized_templates() {
+        let templates = Templates::load(&TemplateConfig::default()).unwrap();
+        let rendered = templates
+            .render(&active_code(Some("https://axum.rs/active/t")), "en-US")
+            .unwrap();
+        assert_eq!(rendered.subject, "Activate your account");
+        assert_eq!(
+            rendered.text.unwrap(),
+            "Your activation code is: 123456\nOr click the link below to activate: https://axum.rs/active/t"
+        );
+        assert!(rendered.html.unwrap().contains("Activate now"));
+    }
+
+    #[test]
+    fn test_override_dir() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let locale_dir = dir.join("active_code").join("en");
+        std::fs::create_dir_all(&locale_dir).unwrap();
+        std::fs::write(locale_dir.join("subject.txt"), "Activate {{ email }}").unwrap();
+        std::fs::write(locale_dir.join("html.html"), "<p>{{ code }}</p>").unwrap();
+
+        let templates = Templates::load(&TemplateConfig {
+            dir: dir.to_string_lossy().to_string(),
+        })
+        .unwrap();
+        let rendered = templates.render(&active_code(None), "en").unwrap();
+        assert_eq!(rendered.subject, "Activate team@axum.rs");
+        assert_eq!(rendered.text, None);
+        assert_eq!(rendered.html.unwrap(), "<p>123456</p>");
+
+        // 目录中没有的仍使用内置模板
+        let rendered = templates.render(&active_code(None), "zh-CN").unwrap();
+        assert_eq!(rendered.subject, "激活账号");
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
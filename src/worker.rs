@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{
+    broker::MessageBroker,
+    email::Mailer,
+    i18n::DEFAULT_LOCALE,
+    message::{Envelope, Registry},
+    model::{self, user::ActiveCode},
+    rabbitmq::{
+        consumer::Supervisor,
+        handler::{self, Handler},
+    },
+    template::Templates,
+    Result, SmtpClass, WorkerConfig,
+};
+
+/// 启动发送激活邮件的消费者
+pub fn start(cfg: &WorkerConfig, broker: &dyn MessageBroker) -> Result<Supervisor> {
+    let mut registry = Registry::default();
+    ActiveCode::register(&mut registry);
+
+    Ok(broker.subscribe(
+        "MAIL",
+        &cfg.rabbitmq.routing_key,
+        handler::raw::<ActiveCode, _>(Arc::new(registry), Arc::new(ActivationMailer::new(cfg)?)),
+    ))
+}
+
+/// 发送激活邮件
+pub struct ActivationMailer {
+    from: String,
+    mailer: Mailer,
+    templates: Templates,
+}
+
+impl ActivationMailer {
+    pub fn new(cfg: &WorkerConfig) -> Result<Self> {
+        Ok(Self {
+            from: cfg.email.username.clone(),
+            mailer: Mailer::new(&cfg.email)?,
+            templates: Templates::load(&cfg.template)?,
+        })
+    }
+
+    /// 发送邮件使用的发件人
+    pub fn mailer(&self) -> &Mailer {
+        &self.mailer
+    }
+}
+
+#[async_trait]
+impl Handler<ActiveCode> for ActivationMailer {
+    async fn handle(&self, msg: Envelope<ActiveCode>) -> Result<()> {
+        let ac = msg.payload;
+        let rendered = self
+            .templates
+            .render(&ac, ac.locale.as_deref().unwrap_or(DEFAULT_LOCALE))?;
+        let resp = self
+            .mailer
+            .send(&model::email::Email {
+                from: self.from.clone(),
+                to: vec![ac.email.clone()],
+                subject: rendered.subject,
+                body: rendered.text.unwrap_or_default(),
+                html: rendered.html,
+                ..Default::default()
+            })
+            .await;
+
+        match resp {
+            Ok(()) => {
+                tracing::info!("激活邮件已发送：{}", ac.email);
+                Ok(())
+            }
+            Err(mut err) => {
+                if err.smtp_class() == Some(SmtpClass::Recipient) {
+                    tracing::warn!("收件人被拒收：{}", ac.email);
+                }
+                err.message = format!("发送邮件失败：{}", err.message);
+                Err(err)
+            }
+        }
+    }
+}